@@ -0,0 +1,2 @@
+alter table users
+    add token_version integer default 0 not null;
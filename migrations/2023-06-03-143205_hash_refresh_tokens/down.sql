@@ -0,0 +1,18 @@
+drop index user_tokens_family_id_index;
+
+drop index user_tokens_token_hash_uindex;
+
+-- Hashes cannot be turned back into tokens, so every session is dropped
+delete from user_tokens;
+
+alter table user_tokens
+    rename column token_hash to refresh_token;
+
+alter table user_tokens
+    drop column rotated;
+
+alter table user_tokens
+    drop column expiration_date;
+
+alter table user_tokens
+    drop column family_id;
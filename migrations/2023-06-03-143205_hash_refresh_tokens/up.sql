@@ -0,0 +1,30 @@
+alter table user_tokens
+    add family_id varchar(32);
+
+alter table user_tokens
+    add expiration_date timestamp;
+
+alter table user_tokens
+    add rotated boolean default false not null;
+
+-- Keep existing sessions alive: hash the stored plaintext tokens in place
+-- and give each of them its own family and a fresh lifetime
+update user_tokens
+set refresh_token   = encode(sha256(refresh_token::bytea), 'hex'),
+    family_id       = md5(random()::text),
+    expiration_date = now() + interval '30 days';
+
+alter table user_tokens
+    alter column family_id set not null;
+
+alter table user_tokens
+    alter column expiration_date set not null;
+
+alter table user_tokens
+    rename column refresh_token to token_hash;
+
+create unique index user_tokens_token_hash_uindex
+    on user_tokens (token_hash);
+
+create index user_tokens_family_id_index
+    on user_tokens (family_id);
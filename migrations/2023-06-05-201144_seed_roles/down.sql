@@ -0,0 +1,15 @@
+delete from user_roles
+where role_id in (select id from roles where name in ('admin', 'moderator'));
+
+delete from roles
+where name in ('admin', 'moderator');
+
+alter table roles
+    drop constraint roles_name_uindex;
+
+alter table user_roles
+    drop constraint user_roles_pk;
+
+alter table user_roles
+    add constraint user_roles_pk
+        primary key (user_id);
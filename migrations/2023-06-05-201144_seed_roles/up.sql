@@ -0,0 +1,16 @@
+-- A user can have more than one role
+alter table user_roles
+    drop constraint user_roles_pk;
+
+alter table user_roles
+    add constraint user_roles_pk
+        primary key (user_id, role_id);
+
+alter table roles
+    add constraint roles_name_uindex
+        unique (name);
+
+insert into roles (name, description)
+values ('admin', 'Full access to administrative features'),
+       ('moderator', 'Can moderate user generated content')
+on conflict (name) do nothing;
@@ -0,0 +1,15 @@
+drop index user_access_ip_address_access_date_index;
+
+drop index user_access_user_id_access_date_index;
+
+alter table user_access
+    drop column user_agent;
+
+alter table user_access
+    drop column ip_address;
+
+delete from user_access
+where user_id is null;
+
+alter table user_access
+    alter column user_id set not null;
@@ -0,0 +1,18 @@
+-- Failed attempts with an unknown login or email are recorded without a user
+alter table user_access
+    alter column user_id drop default;
+
+alter table user_access
+    alter column user_id drop not null;
+
+alter table user_access
+    add ip_address varchar(45);
+
+alter table user_access
+    add user_agent varchar;
+
+create index user_access_user_id_access_date_index
+    on user_access (user_id, access_date);
+
+create index user_access_ip_address_access_date_index
+    on user_access (ip_address, access_date);
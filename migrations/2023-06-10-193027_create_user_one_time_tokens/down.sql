@@ -0,0 +1 @@
+drop table user_one_time_tokens;
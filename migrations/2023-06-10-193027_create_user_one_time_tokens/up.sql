@@ -0,0 +1,17 @@
+create table user_one_time_tokens
+(
+    id              serial,
+    user_id         integer                 not null,
+    purpose         varchar(20)             not null,
+    token_hash      varchar                 not null,
+    expiration_date timestamp               not null,
+    used_date       timestamp,
+    created_date    timestamp default now() not null,
+    constraint user_one_time_tokens_pk
+        primary key (id),
+    constraint user_one_time_tokens_users_id_fk
+        foreign key (user_id) references users
+);
+
+create unique index user_one_time_tokens_token_hash_uindex
+    on user_one_time_tokens (token_hash);
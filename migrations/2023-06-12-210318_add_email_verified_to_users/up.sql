@@ -0,0 +1,6 @@
+alter table users
+    add email_verified boolean default false not null;
+
+-- Accounts created before verification existed are trusted as they are
+update users
+set email_verified = true;
@@ -0,0 +1,8 @@
+drop table user_recovery_codes;
+
+alter table users
+    drop column totp_last_used_step;
+alter table users
+    drop column totp_enabled;
+alter table users
+    drop column totp_secret;
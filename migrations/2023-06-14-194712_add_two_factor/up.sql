@@ -0,0 +1,21 @@
+alter table users
+    add totp_secret varchar;
+alter table users
+    add totp_enabled boolean default false not null;
+alter table users
+    add totp_last_used_step bigint;
+
+create table user_recovery_codes
+(
+    id        serial,
+    user_id   integer not null,
+    code_hash varchar not null,
+    used_date timestamp,
+    constraint user_recovery_codes_pk
+        primary key (id),
+    constraint user_recovery_codes_users_id_fk
+        foreign key (user_id) references users
+);
+
+create index user_recovery_codes_user_id_index
+    on user_recovery_codes (user_id);
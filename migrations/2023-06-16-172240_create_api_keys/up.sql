@@ -0,0 +1,18 @@
+create table api_keys
+(
+    id             serial,
+    name           varchar(64)             not null,
+    key_prefix     varchar(12)             not null,
+    key_hash       varchar                 not null,
+    scopes         text[]                  not null,
+    created_by     integer                 not null,
+    created_date   timestamp default now() not null,
+    last_used_date timestamp,
+    constraint api_keys_pk
+        primary key (id),
+    constraint api_keys_users_id_fk
+        foreign key (created_by) references users
+);
+
+create unique index api_keys_key_hash_uindex
+    on api_keys (key_hash);
@@ -0,0 +1,10 @@
+drop index user_tokens_user_id_index;
+
+alter table user_tokens
+    drop column user_agent;
+alter table user_tokens
+    drop column ip_address;
+alter table user_tokens
+    drop column last_used_date;
+alter table user_tokens
+    drop column created_date;
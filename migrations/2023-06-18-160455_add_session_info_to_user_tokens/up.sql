@@ -0,0 +1,11 @@
+alter table user_tokens
+    add created_date timestamp default now() not null;
+alter table user_tokens
+    add last_used_date timestamp;
+alter table user_tokens
+    add ip_address varchar(45);
+alter table user_tokens
+    add user_agent varchar;
+
+create index user_tokens_user_id_index
+    on user_tokens (user_id);
@@ -0,0 +1,3 @@
+drop index users_email_lower_uindex;
+
+drop index users_login_lower_uindex;
@@ -0,0 +1,10 @@
+update users
+set login = trim(login),
+    email = lower(trim(email));
+
+-- Fails if two accounts only differ in case, which has to be resolved by hand
+create unique index users_login_lower_uindex
+    on users (lower(login));
+
+create unique index users_email_lower_uindex
+    on users (lower(email));
@@ -0,0 +1,2 @@
+alter table users
+    add disabled boolean default false not null;
@@ -0,0 +1,2 @@
+drop table oidc_auth_requests;
+drop table user_identities;
@@ -0,0 +1,35 @@
+create table user_identities
+(
+    id           serial,
+    user_id      integer                 not null,
+    provider     varchar(32)             not null,
+    subject      varchar                 not null,
+    email        varchar,
+    created_date timestamp default now() not null,
+    constraint user_identities_pk
+        primary key (id),
+    constraint user_identities_users_id_fk
+        foreign key (user_id) references users
+);
+
+create unique index user_identities_provider_subject_uindex
+    on user_identities (provider, subject);
+
+create index user_identities_user_id_index
+    on user_identities (user_id);
+
+create table oidc_auth_requests
+(
+    id              serial,
+    state_hash      varchar                 not null,
+    provider        varchar(32)             not null,
+    code_verifier   varchar                 not null,
+    nonce           varchar                 not null,
+    expiration_date timestamp               not null,
+    created_date    timestamp default now() not null,
+    constraint oidc_auth_requests_pk
+        primary key (id)
+);
+
+create unique index oidc_auth_requests_state_hash_uindex
+    on oidc_auth_requests (state_hash);
@@ -0,0 +1,6 @@
+drop table user_notification_opt_outs;
+
+alter table user_notification_settings
+    drop column timezone,
+    drop column quiet_hours_start,
+    drop column quiet_hours_end;
@@ -0,0 +1,21 @@
+alter table user_notification_settings
+    add timezone          varchar(64) default 'UTC' not null,
+    add quiet_hours_start time,
+    add quiet_hours_end   time;
+
+create table user_notification_opt_outs
+(
+    user_id           integer     not null,
+    channel           varchar(20) not null,
+    notification_type varchar(20) not null,
+    constraint user_notification_opt_outs_pk
+        primary key (user_id, channel, notification_type),
+    constraint user_notification_opt_outs_user_id_fk
+        foreign key (user_id) references users
+);
+
+-- Settings are created at signup from now on
+insert into user_notification_settings (user_id)
+select id
+from users
+on conflict do nothing;
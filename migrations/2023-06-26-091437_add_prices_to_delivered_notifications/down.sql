@@ -0,0 +1,3 @@
+alter table delivered_notifications
+    drop column old_price,
+    drop column new_price;
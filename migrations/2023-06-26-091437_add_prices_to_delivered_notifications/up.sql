@@ -0,0 +1,3 @@
+alter table delivered_notifications
+    add old_price real,
+    add new_price real;
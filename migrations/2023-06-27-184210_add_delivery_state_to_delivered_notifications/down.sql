@@ -0,0 +1,7 @@
+drop index delivered_notifications_next_attempt_date_index;
+
+alter table delivered_notifications
+    drop column attempts,
+    drop column next_attempt_date,
+    drop column last_error,
+    drop column sent_channels;
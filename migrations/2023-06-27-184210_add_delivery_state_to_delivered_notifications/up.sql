@@ -0,0 +1,10 @@
+-- Notifications recorded before there was a delivery are never sent
+alter table delivered_notifications
+    add attempts          integer       default 0    not null,
+    add next_attempt_date timestamp,
+    add last_error        varchar(255),
+    add sent_channels     varchar(20)[] default '{}' not null;
+
+create index delivered_notifications_next_attempt_date_index
+    on delivered_notifications (next_attempt_date)
+    where delivered = false;
@@ -0,0 +1,2 @@
+alter table user_notification_settings
+    drop column last_digest_date;
@@ -0,0 +1,2 @@
+alter table user_notification_settings
+    add last_digest_date timestamp;
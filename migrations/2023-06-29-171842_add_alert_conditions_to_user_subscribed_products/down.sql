@@ -0,0 +1,8 @@
+alter table delivered_notifications
+    drop column store_id;
+
+alter table user_subscribed_products
+    drop column target_price,
+    drop column min_drop_percent,
+    drop column store_ids,
+    drop column retail_chain_ids;
@@ -0,0 +1,11 @@
+alter table user_subscribed_products
+    add target_price     real,
+    add min_drop_percent real,
+    add store_ids        integer[] default '{}' not null,
+    add retail_chain_ids integer[] default '{}' not null;
+
+-- The store with the new minimum price
+alter table delivered_notifications
+    add store_id integer,
+    add constraint delivered_notifications_stores_id_fk
+        foreign key (store_id) references stores;
@@ -0,0 +1,4 @@
+drop index delivered_notifications_unread_index;
+
+alter table delivered_notifications
+    drop column read_date;
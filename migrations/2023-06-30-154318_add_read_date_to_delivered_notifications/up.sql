@@ -0,0 +1,7 @@
+-- Null until the user reads the notification in the app
+alter table delivered_notifications
+    add read_date timestamp;
+
+create index delivered_notifications_unread_index
+    on delivered_notifications (subscribe_id)
+    where read_date is null;
@@ -0,0 +1,3 @@
+drop trigger product_store_prices_notify on product_store_prices;
+
+drop function notify_product_store_price();
@@ -0,0 +1,26 @@
+-- Every backend instance listens on the channel and streams new prices to its clients
+create function notify_product_store_price() returns trigger as
+$$
+begin
+    perform pg_notify('product_store_prices', json_build_object(
+            'id', new.id,
+            'product_id', product_stores.product_id,
+            'store_id', product_stores.store_id,
+            'store_name', stores.name,
+            'product_store_id', new.product_store_id,
+            'price', new.price,
+            'created_date', new.created_date
+        )::text)
+    from product_stores
+             join stores on stores.id = product_stores.store_id
+    where product_stores.id = new.product_store_id;
+
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger product_store_prices_notify
+    after insert
+    on product_store_prices
+    for each row
+execute function notify_product_store_price();
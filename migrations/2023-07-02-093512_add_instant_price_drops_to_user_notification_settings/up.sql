@@ -0,0 +1,3 @@
+-- Price drops keep going out right away unless the user chooses to get them in the digest
+alter table user_notification_settings
+    add instant_price_drops boolean default true not null;
@@ -0,0 +1,2 @@
+alter table user_notification_settings
+    drop column digest_lease_until;
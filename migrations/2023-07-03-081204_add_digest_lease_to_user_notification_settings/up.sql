@@ -0,0 +1,4 @@
+-- Set while an instance sends the user's digest, so others skip it without the
+-- settings row staying locked for the whole send
+alter table user_notification_settings
+    add digest_lease_until timestamp;
@@ -1,23 +1,76 @@
 use crate::config::app::Config;
-use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::mailer::Mailer;
+use crate::middlewares::jwt_middleware::{AuthUser, UserCache};
+use crate::middlewares::session_cookies;
 use crate::models::response::ResponseBody;
-use crate::models::user::{LoginDTO, UserDTO};
+use crate::models::two_factor::{DisableTwoFactorDTO, TwoFactorCodeDTO, TwoFactorLoginDTO};
+use crate::models::user::{
+    ChangePasswordDTO, DeleteAccountDTO, LoginDTO, PasswordResetConfirmDTO,
+    PasswordResetRequestDTO, UpdateProfileDTO, UserDTO, VerifyEmailQuery,
+};
+use crate::models::user_access::ClientInfo;
 use crate::models::user_tokens::UserRefreshTokenDTO;
 use crate::services::account_service;
-use actix_web::{get, post, web, HttpResponse, Result};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{delete, get, patch, post, web, HttpRequest, HttpResponse, Result};
 use deadpool_diesel::postgres::Pool;
 
 #[utoipa::path(
     request_body = UserDTO,
     responses(
         (status = 200, description = "Signup successful", body = ResponseLogin),
-        (status = 400, description = "Unknown error"),
+        (status = 400, description = "Some fields are invalid or already registered", body = ResponseValidationErrors),
     ),
     context_path = "/api/user"
 )]
 #[post("/signup")]
-pub async fn signup(user_dto: web::Json<UserDTO>, pool: web::Data<Pool>) -> Result<HttpResponse> {
-    match account_service::signup(user_dto.0, &pool).await {
+pub async fn signup(
+    user_dto: web::Json<UserDTO>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse> {
+    match account_service::signup(user_dto.0, &pool, config, mailer).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(VerifyEmailQuery),
+    responses(
+        (status = 200, description = "Email verified successfully"),
+        (status = 400, description = "Token is invalid or has expired"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/verify-email")]
+pub async fn verify_email(
+    query: web::Query<VerifyEmailQuery>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match account_service::verify_email(query.into_inner().token, &pool, &user_cache).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Verification email sent"),
+        (status = 400, description = "Email is already verified"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/verify-email/resend")]
+pub async fn resend_verification_email(
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse> {
+    match account_service::resend_verification_email(auth_user, &pool, config, mailer).await {
         Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
         Err(err) => Ok(err.response()),
     }
@@ -26,39 +79,426 @@ pub async fn signup(user_dto: web::Json<UserDTO>, pool: web::Data<Pool>) -> Resu
 #[utoipa::path(
     request_body = LoginDTO,
     responses(
-        (status = 200, description = "Login successful", body = ResponseTokens),
+        (status = 200, description = "Login successful, or a challenge if two-factor authentication is enabled. In cookie mode the tokens are set as cookies instead", body = ResponseLoginResult),
+        (status = 400, description = "Some fields are invalid", body = ResponseValidationErrors),
         (status = 401, description = "Login, email or password is wrong"),
+        (status = 429, description = "Too many failed login attempts"),
     ),
     context_path = "/api/user"
 )]
 #[post("/login")]
 pub async fn login(
     login: web::Json<LoginDTO>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let client = ClientInfo::from_request(&req);
+    match account_service::login(login.0, client, &pool, config.clone()).await {
+        Ok(login_result) => Ok(session_cookies::login_response(login_result, &config)),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = TwoFactorLoginDTO,
+    responses(
+        (status = 200, description = "Login successful; in cookie mode the tokens are set as cookies instead", body = ResponseTokens),
+        (status = 401, description = "Challenge is invalid or code is wrong"),
+        (status = 429, description = "Too many failed login attempts"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/login/2fa")]
+pub async fn login_two_factor(
+    two_factor_login: web::Json<TwoFactorLoginDTO>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let client = ClientInfo::from_request(&req);
+    match account_service::login_two_factor(two_factor_login.0, client, &pool, config.clone()).await
+    {
+        Ok(tokens) => Ok(session_cookies::tokens_response(tokens, &config)),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Secret generated, confirm it with a code to enable two-factor authentication", body = ResponseTwoFactorEnrollment),
+        (status = 400, description = "Two-factor authentication is already enabled"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/2fa/enroll")]
+pub async fn enroll_two_factor(
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
     config: web::Data<Config>,
 ) -> Result<HttpResponse> {
-    match account_service::login(login.0, &pool, config).await {
-        Ok(tokens) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", tokens))),
+    match account_service::enroll_two_factor(auth_user, &pool, &user_cache, config).await {
+        Ok(enrollment) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", enrollment))),
         Err(err) => Ok(err.response()),
     }
 }
 
 #[utoipa::path(
-    request_body = UserRefreshTokenDTO,
+    request_body = TwoFactorCodeDTO,
     responses(
-        (status = 200, description = "Refresh successful", body = ResponseTokens),
+        (status = 200, description = "Two-factor authentication enabled", body = ResponseRecoveryCodes),
+        (status = 400, description = "Code is wrong or enrollment wasn't started"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/2fa/confirm")]
+pub async fn confirm_two_factor(
+    auth_user: AuthUser,
+    two_factor_code: web::Json<TwoFactorCodeDTO>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match account_service::confirm_two_factor(auth_user, two_factor_code.0, &pool, &user_cache)
+        .await
+    {
+        Ok(recovery_codes) => {
+            Ok(HttpResponse::Ok().json(ResponseBody::new("success", recovery_codes)))
+        }
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = DisableTwoFactorDTO,
+    responses(
+        (status = 200, description = "Two-factor authentication disabled"),
+        (status = 400, description = "Password or code is wrong"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/2fa/disable")]
+pub async fn disable_two_factor(
+    auth_user: AuthUser,
+    disable: web::Json<DisableTwoFactorDTO>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match account_service::disable_two_factor(auth_user, disable.0, &pool, &user_cache).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body(content = UserRefreshTokenDTO, description = "Not needed in cookie mode"),
+    responses(
+        (status = 200, description = "Refresh successful; in cookie mode the tokens are set as cookies instead", body = ResponseTokens),
+        (status = 401, description = "Refresh token is missing"),
+        (status = 403, description = "CSRF token is missing or invalid"),
         (status = 404, description = "Refresh token not found"),
     ),
     context_path = "/api/user"
 )]
 #[post("/refresh-token")]
 pub async fn refresh_token(
-    user_refresh_token: web::Json<UserRefreshTokenDTO>,
+    user_refresh_token: Option<web::Json<UserRefreshTokenDTO>>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let user_refresh_token =
+        session_cookies::refresh_token_from_request(user_refresh_token, &req, &config)?;
+    let client = ClientInfo::from_request(&req);
+    match account_service::refresh_token(
+        user_refresh_token,
+        client,
+        &pool,
+        &user_cache,
+        config.clone(),
+    )
+    .await
+    {
+        Ok(tokens) => Ok(session_cookies::tokens_response(tokens, &config)),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body(content = UserRefreshTokenDTO, description = "Not needed in cookie mode"),
+    responses(
+        (status = 200, description = "Logout successful"),
+        (status = 401, description = "Refresh token is missing"),
+        (status = 403, description = "CSRF token is missing or invalid"),
+        (status = 404, description = "Refresh token not found"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/logout")]
+pub async fn logout(
+    user_refresh_token: Option<web::Json<UserRefreshTokenDTO>>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let user_refresh_token =
+        session_cookies::refresh_token_from_request(user_refresh_token, &req, &config)?;
+    // The cookies are cleared even if the session is already gone
+    let (mut response, body) =
+        match account_service::logout(user_refresh_token, &pool, &user_cache).await {
+            Ok(_) => (
+                HttpResponse::Ok(),
+                ResponseBody::new("success", "".to_string()),
+            ),
+            Err(err) => (HttpResponse::build(err.http_status), err.body),
+        };
+    session_cookies::clear_session_cookies(&mut response, &config);
+    Ok(response.json(body))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Logged out from all devices"),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/logout-all")]
+pub async fn logout_all(
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    match account_service::logout_all(auth_user, &pool, &user_cache).await {
+        Ok(_) => {
+            let mut response = HttpResponse::Ok();
+            session_cookies::clear_session_cookies(&mut response, &config);
+            Ok(response.json(ResponseBody::new("success", "")))
+        }
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Sessions get successful", body = ResponseVecSession),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/sessions")]
+pub async fn sessions(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match account_service::get_sessions(auth_user, &pool).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", sessions))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = String, Path, description = "Session id"),
+    ),
+    responses(
+        (status = 200, description = "Session revoked, its access token is rejected from now on"),
+        (status = 404, description = "Session not found"),
+    ),
+    context_path = "/api/user"
+)]
+#[delete("/sessions/{id}")]
+pub async fn revoke_session(
+    session_id: web::Path<String>,
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match account_service::revoke_session(auth_user, session_id.into_inner(), &pool, &user_cache)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Access log get successful", body = ResponseVecUserAccess),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/access-log")]
+pub async fn access_log(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match account_service::get_access_log(auth_user, &pool).await {
+        Ok(access_log) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", access_log))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = PasswordResetRequestDTO,
+    responses(
+        (status = 200, description = "Reset link sent if the email is registered"),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/password-reset/request")]
+pub async fn request_password_reset(
+    reset_request: web::Json<PasswordResetRequestDTO>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse> {
+    match account_service::request_password_reset(reset_request.0, &pool, config, mailer).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = PasswordResetConfirmDTO,
+    responses(
+        (status = 200, description = "Password reset successful"),
+        (status = 400, description = "Token is invalid or password doesn't meet the requirements"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/password-reset/confirm")]
+pub async fn confirm_password_reset(
+    reset_confirm: web::Json<PasswordResetConfirmDTO>,
     pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
     config: web::Data<Config>,
 ) -> Result<HttpResponse> {
-    match account_service::refresh_token(user_refresh_token.0, &pool, config).await {
-        Ok(tokens) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", tokens))),
+    match account_service::confirm_password_reset(reset_confirm.0, &pool, &user_cache, config).await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Profile get successful", body = ResponseUserProfile),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/me")]
+pub async fn get_profile(auth_user: AuthUser) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ResponseBody::new(
+        "success",
+        account_service::get_profile(auth_user),
+    )))
+}
+
+#[utoipa::path(
+    request_body = UpdateProfileDTO,
+    responses(
+        (status = 200, description = "Profile updated successfully", body = ResponseUserProfile),
+        (status = 400, description = "Nickname is too long or email is already registered"),
+    ),
+    context_path = "/api/user"
+)]
+#[patch("/me")]
+pub async fn update_profile(
+    auth_user: AuthUser,
+    profile: web::Json<UpdateProfileDTO>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse> {
+    match account_service::update_profile(auth_user, profile.0, &pool, &user_cache, config, mailer)
+        .await
+    {
+        Ok(profile) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", profile))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = DeleteAccountDTO,
+    responses(
+        (status = 200, description = "Account deleted successfully"),
+        (status = 400, description = "Password is wrong or API keys created by the account are still active"),
+    ),
+    context_path = "/api/user"
+)]
+#[delete("/me")]
+pub async fn delete_account(
+    auth_user: AuthUser,
+    deletion: web::Json<DeleteAccountDTO>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    match account_service::delete_account(auth_user, deletion.0, &pool, &user_cache).await {
+        Ok(_) => {
+            let mut response = HttpResponse::Ok();
+            session_cookies::clear_session_cookies(&mut response, &config);
+            Ok(response.json(ResponseBody::new("success", "")))
+        }
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "JSON archive of all the user's data"),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/export")]
+pub async fn export_data(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match account_service::export_data(auth_user, &pool).await {
+        Ok(export) => Ok(HttpResponse::Ok()
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(
+                    "price-tracker-export.json".to_string(),
+                )],
+            })
+            .json(ResponseBody::new("success", export))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = ChangePasswordDTO,
+    responses(
+        (status = 200, description = "Password changed successfully, with new tokens for this session; in cookie mode they are set as cookies instead", body = ResponseTokens),
+        (status = 400, description = "Current password is wrong or new password doesn't meet the requirements"),
+        (status = 429, description = "Too many failed login attempts"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/change-password")]
+pub async fn change_password(
+    auth_user: AuthUser,
+    change: web::Json<ChangePasswordDTO>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let client = ClientInfo::from_request(&req);
+    match account_service::change_password(
+        auth_user,
+        change.0,
+        client,
+        &pool,
+        &user_cache,
+        config.clone(),
+    )
+    .await
+    {
+        // Every other session was logged out, this one continues with the new tokens
+        Ok(tokens) => Ok(session_cookies::tokens_response(tokens, &config)),
         Err(err) => Ok(err.response()),
     }
 }
@@ -70,10 +510,10 @@ pub async fn refresh_token(
     context_path = "/api/user"
 )]
 #[get("/password-requirements")]
-pub async fn password_requirements() -> Result<HttpResponse> {
+pub async fn password_requirements(config: web::Data<Config>) -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(ResponseBody::new(
         "success",
-        account_service::get_password_requirements(),
+        account_service::get_password_requirements(config),
     )))
 }
 
@@ -84,11 +524,8 @@ pub async fn password_requirements() -> Result<HttpResponse> {
     context_path = "/api/user"
 )]
 #[get("/subscriptions")]
-pub async fn subscriptions(
-    token_claims: TokenClaims,
-    pool: web::Data<Pool>,
-) -> Result<HttpResponse> {
-    match account_service::get_subscriptions(token_claims, &pool).await {
+pub async fn subscriptions(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match account_service::get_subscriptions(auth_user, &pool).await {
         Ok(subscriptions) => {
             Ok(HttpResponse::Ok().json(ResponseBody::new("success", subscriptions)))
         }
@@ -0,0 +1,182 @@
+use crate::middlewares::jwt_middleware::UserCache;
+use crate::middlewares::role_middleware::{Admin, Moderator, RequireRole};
+use crate::models::api_key::CreateApiKeyDTO;
+use crate::models::response::ResponseBody;
+use crate::services::admin_service;
+use actix_web::{delete, get, post, put, web, HttpResponse, Result};
+use deadpool_diesel::postgres::Pool;
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Got a role list", body = ResponseVecRole),
+        (status = 403, description = "Moderator role is required"),
+    ),
+    context_path = "/api/admin"
+)]
+#[get("/roles")]
+pub async fn roles(
+    _moderator: RequireRole<Moderator>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match admin_service::roles(&pool).await {
+        Ok(roles) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", roles))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = i32, Path, description = "User id"),
+        ("role" = String, Path, description = "Role name"),
+    ),
+    responses(
+        (status = 200, description = "Role successfully assigned"),
+        (status = 400, description = "Unknown error"),
+        (status = 403, description = "Admin role is required"),
+        (status = 404, description = "User not found"),
+    ),
+    context_path = "/api/admin"
+)]
+#[put("/user/{id}/role/{role}")]
+pub async fn assign_role(
+    path: web::Path<(i32, String)>,
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match admin_service::assign_role(path, &pool).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = i32, Path, description = "User id"),
+        ("role" = String, Path, description = "Role name"),
+    ),
+    responses(
+        (status = 200, description = "Role revoked and the user signed out everywhere"),
+        (status = 400, description = "Unknown error"),
+        (status = 403, description = "Admin role is required"),
+    ),
+    context_path = "/api/admin"
+)]
+#[delete("/user/{id}/role/{role}")]
+pub async fn revoke_role(
+    path: web::Path<(i32, String)>,
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match admin_service::revoke_role(path, &pool, &user_cache).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = i32, Path, description = "User id"),
+    ),
+    responses(
+        (status = 200, description = "User disabled and signed out everywhere"),
+        (status = 403, description = "Admin role is required"),
+        (status = 404, description = "User not found"),
+    ),
+    context_path = "/api/admin"
+)]
+#[put("/user/{id}/disabled")]
+pub async fn disable_user(
+    user_id: web::Path<i32>,
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match admin_service::set_user_disabled(user_id, true, &pool, &user_cache).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = i32, Path, description = "User id"),
+    ),
+    responses(
+        (status = 200, description = "User enabled"),
+        (status = 403, description = "Admin role is required"),
+        (status = 404, description = "User not found"),
+    ),
+    context_path = "/api/admin"
+)]
+#[delete("/user/{id}/disabled")]
+pub async fn enable_user(
+    user_id: web::Path<i32>,
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+    user_cache: web::Data<UserCache>,
+) -> Result<HttpResponse> {
+    match admin_service::set_user_disabled(user_id, false, &pool, &user_cache).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Got an API key list", body = ResponseVecApiKey),
+        (status = 403, description = "Admin role is required"),
+    ),
+    context_path = "/api/admin"
+)]
+#[get("/api-keys")]
+pub async fn api_keys(_admin: RequireRole<Admin>, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match admin_service::api_keys(&pool).await {
+        Ok(api_keys) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", api_keys))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateApiKeyDTO,
+    responses(
+        (status = 200, description = "API key created, the key is only shown once", body = ResponseCreatedApiKey),
+        (status = 400, description = "Name is invalid or a scope is unknown"),
+        (status = 403, description = "Admin role is required"),
+    ),
+    context_path = "/api/admin"
+)]
+#[post("/api-keys")]
+pub async fn create_api_key(
+    admin: RequireRole<Admin>,
+    api_key: web::Json<CreateApiKeyDTO>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match admin_service::create_api_key(admin.auth_user, api_key.0, &pool).await {
+        Ok(created) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", created))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    params(
+        ("id" = i32, Path, description = "API key id"),
+    ),
+    responses(
+        (status = 200, description = "API key revoked"),
+        (status = 403, description = "Admin role is required"),
+        (status = 404, description = "API key not found"),
+    ),
+    context_path = "/api/admin"
+)]
+#[delete("/api-keys/{id}")]
+pub async fn revoke_api_key(
+    api_key_id: web::Path<i32>,
+    _admin: RequireRole<Admin>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match admin_service::revoke_api_key(api_key_id, &pool).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
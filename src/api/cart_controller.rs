@@ -1,4 +1,4 @@
-use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::middlewares::jwt_middleware::AuthUser;
 use crate::models::response::ResponseBody;
 use crate::models::user::UserShoppingCartDTO;
 use crate::services::cart_service;
@@ -16,10 +16,10 @@ use deadpool_diesel::postgres::Pool;
 #[put("/add")]
 pub async fn add_to_cart(
     cart_dto: web::Json<UserShoppingCartDTO>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match cart_service::add_to_cart(cart_dto, token_claims, &pool).await {
+    match cart_service::add_to_cart(cart_dto, auth_user, &pool).await {
         Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
         Err(err) => Ok(err.response()),
     }
@@ -33,8 +33,8 @@ pub async fn add_to_cart(
         context_path = "/api/cart"
     )]
 #[get("")]
-pub async fn get_cart(token_claims: TokenClaims, pool: web::Data<Pool>) -> Result<HttpResponse> {
-    match cart_service::get_cart(token_claims, &pool).await {
+pub async fn get_cart(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match cart_service::get_cart(auth_user, &pool).await {
         Ok(cart) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", cart))),
         Err(err) => Ok(err.response()),
     }
@@ -49,10 +49,10 @@ pub async fn get_cart(token_claims: TokenClaims, pool: web::Data<Pool>) -> Resul
     )]
 #[get("/total")]
 pub async fn get_cart_total_price(
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match cart_service::get_cart_total_price(token_claims, &pool).await {
+    match cart_service::get_cart_total_price(auth_user, &pool).await {
         Ok(total_price) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", total_price))),
         Err(err) => Ok(err.response()),
     }
@@ -1,4 +1,4 @@
-use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::middlewares::jwt_middleware::AuthUser;
 use crate::models::response::ResponseBody;
 use crate::models::user::HistoryDTO;
 use crate::services::history_service;
@@ -16,10 +16,10 @@ use deadpool_diesel::postgres::Pool;
 #[post("/history")]
 pub async fn add_to_history(
     history_dto: web::Json<HistoryDTO>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match history_service::add_to_history(history_dto, token_claims, &pool).await {
+    match history_service::add_to_history(history_dto, auth_user, &pool).await {
         Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
         Err(err) => Ok(err.response()),
     }
@@ -33,8 +33,8 @@ pub async fn add_to_history(
     context_path = "/api"
 )]
 #[get("/history")]
-pub async fn get_history(token_claims: TokenClaims, pool: web::Data<Pool>) -> Result<HttpResponse> {
-    match history_service::get_history(token_claims, &pool).await {
+pub async fn get_history(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match history_service::get_history(auth_user, &pool).await {
         Ok(history) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", history))),
         Err(err) => Ok(err.response()),
     }
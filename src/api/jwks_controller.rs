@@ -0,0 +1,14 @@
+use crate::config::app::Config;
+use actix_web::{get, web, HttpResponse, Result};
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Public keys for verifying access tokens, as a JWK set"),
+    ),
+    context_path = "/api"
+)]
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(config: web::Data<Config>) -> Result<HttpResponse> {
+    // Served as is rather than wrapped in ResponseBody, since JWKS clients expect this format
+    Ok(HttpResponse::Ok().json(config.jwt_keys.jwks()))
+}
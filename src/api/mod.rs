@@ -1,6 +1,12 @@
 pub mod account_controller;
+pub mod admin_controller;
 pub mod cart_controller;
 pub mod category_controller;
 pub mod history_controller;
+pub mod jwks_controller;
+pub mod notification_controller;
+pub mod oidc_controller;
 pub mod ping_controller;
 pub mod product_controller;
+pub mod settings_controller;
+pub mod stream_controller;
@@ -0,0 +1,63 @@
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::notification::InboxFilter;
+use crate::models::response::ResponseBody;
+use crate::services::notification_service;
+use actix_web::{get, post, web, HttpResponse, Result};
+use deadpool_diesel::postgres::Pool;
+
+#[utoipa::path(
+    params(InboxFilter),
+    responses(
+        (status = 200, description = "Got a page of the user's notifications, newest first", body = ResponseInbox),
+        (status = 400, description = "Invalid page or page size", body = ResponseValidationErrors),
+    ),
+    context_path = "/api"
+)]
+#[get("/notifications")]
+pub async fn get_notifications(
+    filter: web::Query<InboxFilter>,
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match notification_service::get_inbox(filter.into_inner(), auth_user, &pool).await {
+        Ok(inbox) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", inbox))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "The notification is read"),
+        (status = 404, description = "The user has no such notification"),
+    ),
+    context_path = "/api"
+)]
+#[post("/notifications/{id}/read")]
+pub async fn mark_notification_read(
+    notification_id: web::Path<i32>,
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match notification_service::mark_read(notification_id, auth_user, &pool).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "All the user's notifications are read"),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api"
+)]
+#[post("/notifications/read-all")]
+pub async fn mark_all_notifications_read(
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match notification_service::mark_all_read(auth_user, &pool).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
@@ -0,0 +1,79 @@
+use crate::config::app::Config;
+use crate::middlewares::session_cookies;
+use crate::models::response::ResponseBody;
+use crate::models::user_access::ClientInfo;
+use crate::models::user_identity::OidcCallbackDTO;
+use crate::oidc::OidcClient;
+use crate::services::oidc_service;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use deadpool_diesel::postgres::Pool;
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Names of the providers users can log in with", body = ResponseVecString),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/oidc/providers")]
+pub async fn providers(oidc_client: web::Data<OidcClient>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ResponseBody::new(
+        "success",
+        oidc_service::get_providers(oidc_client),
+    )))
+}
+
+#[utoipa::path(
+    params(
+        ("provider", description = "Name of the provider")
+    ),
+    responses(
+        (status = 200, description = "URL to send the user to. The state is also set as an HttpOnly cookie the callback has to be sent with", body = ResponseOidcAuthorization),
+        (status = 404, description = "Provider is not configured"),
+        (status = 502, description = "Provider could not be reached"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/oidc/{provider}/authorize")]
+pub async fn authorize(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    oidc_client: web::Data<OidcClient>,
+) -> Result<HttpResponse> {
+    match oidc_service::authorize(path.into_inner(), &pool, config.clone(), oidc_client).await {
+        Ok((authorization, state)) => Ok(HttpResponse::Ok()
+            .cookie(session_cookies::oidc_state_cookie(state, &config))
+            .json(ResponseBody::new("success", authorization))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = OidcCallbackDTO,
+    responses(
+        (status = 200, description = "Login successful, or a challenge if two-factor authentication is enabled. In cookie mode the tokens are set as cookies instead", body = ResponseLoginResult),
+        (status = 401, description = "Login request has expired, was started in another browser, or the provider's answer was rejected"),
+        (status = 502, description = "Provider could not be reached"),
+    ),
+    context_path = "/api/user"
+)]
+#[post("/oidc/callback")]
+pub async fn callback(
+    callback: web::Json<OidcCallbackDTO>,
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    oidc_client: web::Data<OidcClient>,
+) -> Result<HttpResponse> {
+    session_cookies::verify_oidc_state(&req, &callback.state)?;
+
+    let client = ClientInfo::from_request(&req);
+    match oidc_service::callback(callback.0, client, &pool, config.clone(), oidc_client).await {
+        Ok(login_result) => {
+            let mut response = session_cookies::login_response(login_result, &config);
+            response.add_cookie(&session_cookies::clear_oidc_state_cookie(&config))?;
+            Ok(response)
+        }
+        Err(err) => Ok(err.response()),
+    }
+}
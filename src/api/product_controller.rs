@@ -1,39 +1,47 @@
-use crate::middlewares::jwt_middleware::TokenClaims;
-use crate::models::product::ProductFilter;
+use crate::middlewares::api_key_middleware::{PricesWrite, RequireScope};
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::product::{NewPriceDTO, ProductFilter, ProductQuery};
 use crate::models::response::ResponseBody;
 use crate::services::product_service;
-use actix_web::{delete, get, put, web, HttpResponse, Result};
+use actix_web::{delete, get, post, put, web, HttpResponse, Result};
 use deadpool_diesel::postgres::Pool;
 
 #[utoipa::path(
     params(ProductFilter),
     responses(
         (status = 200, description = "Got a product list", body = ResponseVecProduct),
-        (status = 400, description = "Unknown error"),
+        (status = 400, description = "The scope needs home settings the user hasn't set"),
     ),
         context_path = "/api"
     )]
 #[get("/products")]
 pub async fn products(
     filters: web::Query<ProductFilter>,
+    auth_user: Option<AuthUser>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match product_service::products(filters, &pool).await {
+    match product_service::products(filters, auth_user, &pool).await {
         Ok(products) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", products))),
         Err(err) => Ok(err.response()),
     }
 }
 
 #[utoipa::path(
+    params(ProductQuery),
     responses(
         (status = 200, description = "Got a product by id", body = ResponseProduct),
-        (status = 400, description = "Unknown error"),
+        (status = 400, description = "Product not found, or the scope needs home settings the user hasn't set"),
     ),
         context_path = "/api"
 )]
 #[get("/product/{id}")]
-pub async fn product(product_id: web::Path<i32>, pool: web::Data<Pool>) -> Result<HttpResponse> {
-    match product_service::product(product_id, &pool).await {
+pub async fn product(
+    product_id: web::Path<i32>,
+    query: web::Query<ProductQuery>,
+    auth_user: Option<AuthUser>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match product_service::product(product_id, query, auth_user, &pool).await {
         Ok(product) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", product))),
         Err(err) => Ok(err.response()),
     }
@@ -67,10 +75,10 @@ pub async fn product_by_product_store_id(
 #[get("/product/{id}/subscription")]
 pub async fn get_product_subscription(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match product_service::get_product_subscription(product_id, token_claims, &pool).await {
+    match product_service::get_product_subscription(product_id, auth_user, &pool).await {
         Ok(product_subscription) => {
             Ok(HttpResponse::Ok().json(ResponseBody::new("success", product_subscription)))
         }
@@ -79,19 +87,22 @@ pub async fn get_product_subscription(
 }
 
 #[utoipa::path(
+    request_body(content = SubscriptionConditionsDTO, description = "Optional; without it, every price drop is notified about"),
     responses(
         (status = 200, description = "Successfully subscribed to product"),
-        (status = 400, description = "Unknown error"),
+        (status = 400, description = "Invalid conditions, or unknown stores or retail chains", body = ResponseValidationErrors),
+        (status = 403, description = "Email is not verified"),
     ),
         context_path = "/api"
 )]
 #[put("/product/{id}/subscribe")]
 pub async fn subscribe_to_product(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    body: web::Bytes,
+    auth_user: AuthUser,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match product_service::subscribe_to_product(product_id, token_claims, &pool).await {
+    match product_service::subscribe_to_product(product_id, body, auth_user, &pool).await {
         Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
         Err(err) => Ok(err.response()),
     }
@@ -107,10 +118,36 @@ pub async fn subscribe_to_product(
 #[delete("/product/{id}/subscribe")]
 pub async fn unsubscribe_from_product(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match product_service::unsubscribe_from_product(product_id, auth_user, &pool).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = NewPriceDTO,
+    params(
+        ("X-Api-Key" = String, Header, description = "API key with the prices:write scope"),
+    ),
+    responses(
+        (status = 200, description = "Price recorded"),
+        (status = 400, description = "Product store not found or price is invalid"),
+        (status = 401, description = "API key is missing or invalid"),
+        (status = 403, description = "API key lacks the prices:write scope"),
+    ),
+    context_path = "/api"
+)]
+#[post("/product-store/{id}/price")]
+pub async fn add_price(
+    product_store_id: web::Path<i32>,
+    new_price: web::Json<NewPriceDTO>,
+    _api_key: RequireScope<PricesWrite>,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse> {
-    match product_service::unsubscribe_from_product(product_id, token_claims, &pool).await {
+    match product_service::add_price(product_store_id, new_price.0, &pool).await {
         Ok(_) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", ""))),
         Err(err) => Ok(err.response()),
     }
@@ -0,0 +1,80 @@
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::response::ResponseBody;
+use crate::models::user_notification_settings::UserNotificationSettingsDTO;
+use crate::models::user_settings::UserSettingsDTO;
+use crate::services::settings_service;
+use actix_web::{get, put, web, HttpResponse, Result};
+use deadpool_diesel::postgres::Pool;
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Got the user's settings, null if they were never set", body = ResponseUserSettings),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/settings")]
+pub async fn get_settings(auth_user: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse> {
+    match settings_service::get_settings(auth_user, &pool).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", settings))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = UserSettingsDTO,
+    responses(
+        (status = 200, description = "Settings updated successfully", body = ResponseUserSettings),
+        (status = 400, description = "The country or city doesn't exist, or the city is in another country", body = ResponseValidationErrors),
+    ),
+    context_path = "/api/user"
+)]
+#[put("/settings")]
+pub async fn update_settings(
+    auth_user: AuthUser,
+    settings: web::Json<UserSettingsDTO>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match settings_service::update_settings(auth_user, settings.0, &pool).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", settings))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Got the user's notification settings", body = ResponseUserNotificationSettings),
+        (status = 400, description = "Unknown error"),
+    ),
+    context_path = "/api/user"
+)]
+#[get("/notification-settings")]
+pub async fn get_notification_settings(
+    auth_user: AuthUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match settings_service::get_notification_settings(auth_user, &pool).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", settings))),
+        Err(err) => Ok(err.response()),
+    }
+}
+
+#[utoipa::path(
+    request_body = UserNotificationSettingsDTO,
+    responses(
+        (status = 200, description = "Notification settings updated successfully", body = ResponseUserNotificationSettings),
+        (status = 400, description = "Invalid frequency, time zone or quiet hours", body = ResponseValidationErrors),
+    ),
+    context_path = "/api/user"
+)]
+#[put("/notification-settings")]
+pub async fn update_notification_settings(
+    auth_user: AuthUser,
+    settings: web::Json<UserNotificationSettingsDTO>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match settings_service::update_notification_settings(auth_user, settings.0, &pool).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(ResponseBody::new("success", settings))),
+        Err(err) => Ok(err.response()),
+    }
+}
@@ -0,0 +1,35 @@
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::product::PriceStreamQuery;
+use crate::price_updates::PriceUpdates;
+use crate::services::stream_service;
+use actix_web::http::header;
+use actix_web::{get, web, Error, HttpResponse, Result};
+use deadpool_diesel::postgres::Pool;
+use futures_util::StreamExt;
+
+#[utoipa::path(
+    params(PriceStreamQuery),
+    responses(
+        (status = 200, description = "Server-sent `price` events with a PriceUpdateDTO, for every price added to the products from now on", content_type = "text/event-stream", body = PriceUpdateDTO),
+        (status = 400, description = "Invalid product ids", body = ResponseValidationErrors),
+        (status = 401, description = "No product ids and no logged in user"),
+    ),
+    context_path = "/api"
+)]
+#[get("/stream/prices")]
+pub async fn stream_prices(
+    query: web::Query<PriceStreamQuery>,
+    auth_user: Option<AuthUser>,
+    price_updates: web::Data<PriceUpdates>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse> {
+    match stream_service::price_stream(query.into_inner(), auth_user, &price_updates, &pool).await {
+        Ok(events) => Ok(HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header((header::CACHE_CONTROL, "no-cache"))
+            // Stops nginx from buffering the events
+            .insert_header(("X-Accel-Buffering", "no"))
+            .streaming(events.map(Ok::<_, Error>))),
+        Err(err) => Ok(err.response()),
+    }
+}
@@ -1,110 +1,570 @@
 use crate::api::*;
+use crate::config::jwt_keys::JwtKeys;
+use crate::config::password_policy::PasswordPolicy;
+use crate::middlewares::api_key_middleware::API_KEY_HEADER;
+use crate::middlewares::session_cookies::CSRF_TOKEN_HEADER;
+use crate::models::api_key::{ApiKeyDTO, CreateApiKeyDTO, CreatedApiKeyDTO};
 use crate::models::category::Category;
-use crate::models::product::{Product, ProductDTO, ProductStoreDTO, ProductStorePriceDTO};
+use crate::models::notification::{InboxDTO, InboxNotificationDTO};
+use crate::models::product::{
+    NewPriceDTO, PriceUpdateDTO, Product, ProductDTO, ProductStoreDTO, ProductStorePriceDTO,
+};
 use crate::models::response::{
-    ResponseCartTotalPrice, ResponsePasswordRequirements, ResponseProduct, ResponseProductStore,
-    ResponseProductSubscription, ResponseSubscriptions, ResponseTokens, ResponseVecCategory,
-    ResponseVecHistory, ResponseVecProduct, ResponseVecShoppingCart,
+    ResponseCartTotalPrice, ResponseCreatedApiKey, ResponseInbox, ResponseLoginResult,
+    ResponseOidcAuthorization, ResponsePasswordRequirements, ResponseProduct, ResponseProductStore,
+    ResponseProductSubscription, ResponseRecoveryCodes, ResponseSubscriptions, ResponseTokens,
+    ResponseTwoFactorEnrollment, ResponseUserNotificationSettings, ResponseUserProfile,
+    ResponseUserSettings, ResponseValidationErrors, ResponseVecApiKey, ResponseVecCategory,
+    ResponseVecHistory, ResponseVecProduct, ResponseVecRole, ResponseVecSession,
+    ResponseVecShoppingCart, ResponseVecString, ResponseVecUserAccess,
+};
+use crate::models::role::Role;
+use crate::models::two_factor::{
+    DisableTwoFactorDTO, RecoveryCodesDTO, TwoFactorChallengeDTO, TwoFactorCodeDTO,
+    TwoFactorEnrollmentDTO, TwoFactorLoginDTO,
 };
 use crate::models::user::HistoryWithProductDTO;
 use crate::models::user::{
-    HistoryDTO, LoginDTO, PasswordRequirements, UserDTO, UserShoppingCartDTO,
+    ChangePasswordDTO, DeleteAccountDTO, HistoryDTO, LoginDTO, LoginResultDTO,
+    PasswordRequirements, PasswordResetConfirmDTO, PasswordResetRequestDTO,
+    SubscriptionConditionsDTO, UpdateProfileDTO, UserDTO, UserProfileDTO, UserShoppingCartDTO,
     UserSubscribedProductDTO,
 };
-use crate::models::user_tokens::{UserRefreshTokenDTO, UserTokensDTO};
+use crate::models::user_access::UserAccessDTO;
+use crate::models::user_identity::{OidcAuthorizationDTO, OidcCallbackDTO};
+use crate::models::user_notification_settings::{
+    NotificationChannelKind, NotificationOptOutDTO, NotificationType, UserNotificationSettingsDTO,
+};
+use crate::models::user_settings::{PriceScope, UserSettingsDTO};
+use crate::models::user_tokens::{SessionDTO, UserRefreshTokenDTO, UserTokensDTO};
+use crate::models::validation::FieldError;
+use crate::oidc::OidcProviderConfig;
 use actix_cors::Cors;
+use actix_web::cookie::SameSite;
+use actix_web::http::header;
 use actix_web::web;
 use std::env;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
 use utoipa::{openapi, OpenApi};
 
 // Env var names
 static APP_HOST: &str = "APP_HOST";
 static APP_PORT: &str = "APP_PORT";
 static DATABASE_URL: &str = "DATABASE_URL";
+static JWT_ALGORITHM: &str = "JWT_ALGORITHM";
 static JWT_SECRET: &str = "JWT_SECRET";
+static JWT_PRIVATE_KEY_PATH: &str = "JWT_PRIVATE_KEY_PATH";
+static JWT_KEY_ID: &str = "JWT_KEY_ID";
+static JWT_PUBLIC_KEYS: &str = "JWT_PUBLIC_KEYS";
 static JWT_EXPIRES_IN_SECS: &str = "JWT_EXPIRES_IN_SECS";
+static REFRESH_TOKEN_EXPIRES_IN_SECS: &str = "REFRESH_TOKEN_EXPIRES_IN_SECS";
+static AUTH_USER_CACHE_TTL_SECS: &str = "AUTH_USER_CACHE_TTL_SECS";
+static LOGIN_MAX_FAILED_ATTEMPTS: &str = "LOGIN_MAX_FAILED_ATTEMPTS";
+static LOGIN_MAX_FAILED_ATTEMPTS_PER_IP: &str = "LOGIN_MAX_FAILED_ATTEMPTS_PER_IP";
+static LOGIN_FAILURE_WINDOW_SECS: &str = "LOGIN_FAILURE_WINDOW_SECS";
+static LOGIN_LOCKOUT_BASE_SECS: &str = "LOGIN_LOCKOUT_BASE_SECS";
+static LOGIN_LOCKOUT_MAX_SECS: &str = "LOGIN_LOCKOUT_MAX_SECS";
+static TRUSTED_PROXIES: &str = "TRUSTED_PROXIES";
+static PASSWORD_RESET_TOKEN_EXPIRES_IN_SECS: &str = "PASSWORD_RESET_TOKEN_EXPIRES_IN_SECS";
+static EMAIL_VERIFICATION_TOKEN_EXPIRES_IN_SECS: &str = "EMAIL_VERIFICATION_TOKEN_EXPIRES_IN_SECS";
+static PASSWORD_MIN_LENGTH: &str = "PASSWORD_MIN_LENGTH";
+static PASSWORD_MAX_LENGTH: &str = "PASSWORD_MAX_LENGTH";
+static PASSWORD_MIN_UPPERCASE: &str = "PASSWORD_MIN_UPPERCASE";
+static PASSWORD_MIN_LOWERCASE: &str = "PASSWORD_MIN_LOWERCASE";
+static PASSWORD_MIN_DIGITS: &str = "PASSWORD_MIN_DIGITS";
+static PASSWORD_MIN_SPECIAL: &str = "PASSWORD_MIN_SPECIAL";
+static PASSWORD_BLOCKLIST_PATH: &str = "PASSWORD_BLOCKLIST_PATH";
+static TWO_FACTOR_ISSUER: &str = "TWO_FACTOR_ISSUER";
+static TWO_FACTOR_CHALLENGE_EXPIRES_IN_SECS: &str = "TWO_FACTOR_CHALLENGE_EXPIRES_IN_SECS";
+static AUTH_COOKIES: &str = "AUTH_COOKIES";
+static AUTH_COOKIE_SECURE: &str = "AUTH_COOKIE_SECURE";
+static AUTH_COOKIE_SAME_SITE: &str = "AUTH_COOKIE_SAME_SITE";
+static AUTH_COOKIE_DOMAIN: &str = "AUTH_COOKIE_DOMAIN";
+static CORS_ALLOWED_ORIGINS: &str = "CORS_ALLOWED_ORIGINS";
+static FRONTEND_URL: &str = "FRONTEND_URL";
+static OIDC_PROVIDERS: &str = "OIDC_PROVIDERS";
+static OIDC_REDIRECT_URL: &str = "OIDC_REDIRECT_URL";
+static OIDC_AUTH_REQUEST_EXPIRES_IN_SECS: &str = "OIDC_AUTH_REQUEST_EXPIRES_IN_SECS";
+static MAIL_TRANSPORT: &str = "MAIL_TRANSPORT";
+static MAIL_FROM: &str = "MAIL_FROM";
+static MAIL_OUTBOX_PATH: &str = "MAIL_OUTBOX_PATH";
+static SMTP_HOST: &str = "SMTP_HOST";
+static SMTP_PORT: &str = "SMTP_PORT";
+static SMTP_USERNAME: &str = "SMTP_USERNAME";
+static SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+static SMTP_TLS: &str = "SMTP_TLS";
+static PRICE_CHECK_INTERVAL_SECS: &str = "PRICE_CHECK_INTERVAL_SECS";
+static NOTIFICATION_CHANNELS: &str = "NOTIFICATION_CHANNELS";
+static NOTIFICATION_LOG_PATH: &str = "NOTIFICATION_LOG_PATH";
+static NOTIFICATION_DELIVERY_INTERVAL_SECS: &str = "NOTIFICATION_DELIVERY_INTERVAL_SECS";
+static NOTIFICATION_MAX_ATTEMPTS: &str = "NOTIFICATION_MAX_ATTEMPTS";
+static NOTIFICATION_RETRY_BASE_SECS: &str = "NOTIFICATION_RETRY_BASE_SECS";
+static DIGEST_INTERVAL_SECS: &str = "DIGEST_INTERVAL_SECS";
 
 // Env defaults
 static APP_HOST_DEFAULT: &str = "0.0.0.0";
+static JWT_ALGORITHM_DEFAULT: &str = "HS256";
+static REFRESH_TOKEN_EXPIRES_IN_SECS_DEFAULT: &str = "2592000"; // 30 days
+static AUTH_USER_CACHE_TTL_SECS_DEFAULT: &str = "10";
+static LOGIN_MAX_FAILED_ATTEMPTS_DEFAULT: &str = "5";
+static LOGIN_MAX_FAILED_ATTEMPTS_PER_IP_DEFAULT: &str = "20";
+static LOGIN_FAILURE_WINDOW_SECS_DEFAULT: &str = "86400"; // 1 day
+static LOGIN_LOCKOUT_BASE_SECS_DEFAULT: &str = "30";
+static LOGIN_LOCKOUT_MAX_SECS_DEFAULT: &str = "3600"; // 1 hour
+static PASSWORD_RESET_TOKEN_EXPIRES_IN_SECS_DEFAULT: &str = "3600"; // 1 hour
+static EMAIL_VERIFICATION_TOKEN_EXPIRES_IN_SECS_DEFAULT: &str = "172800"; // 2 days
+static PASSWORD_MIN_LENGTH_DEFAULT: &str = "8";
+static PASSWORD_MAX_LENGTH_DEFAULT: &str = "32";
+static PASSWORD_MIN_UPPERCASE_DEFAULT: &str = "1";
+static PASSWORD_MIN_LOWERCASE_DEFAULT: &str = "1";
+static PASSWORD_MIN_DIGITS_DEFAULT: &str = "1";
+static PASSWORD_MIN_SPECIAL_DEFAULT: &str = "1";
+static TWO_FACTOR_ISSUER_DEFAULT: &str = "Price Tracker";
+static TWO_FACTOR_CHALLENGE_EXPIRES_IN_SECS_DEFAULT: &str = "300"; // 5 minutes
+static AUTH_COOKIES_DEFAULT: &str = "false";
+static AUTH_COOKIE_SECURE_DEFAULT: &str = "true";
+static AUTH_COOKIE_SAME_SITE_DEFAULT: &str = "Strict";
+static FRONTEND_URL_DEFAULT: &str = "http://localhost:3000";
+static OIDC_SCOPES_DEFAULT: &str = "openid email profile";
+static OIDC_AUTH_REQUEST_EXPIRES_IN_SECS_DEFAULT: &str = "600"; // 10 minutes
+static MAIL_TRANSPORT_DEFAULT: &str = "log";
+static MAIL_FROM_DEFAULT: &str = "Price Tracker <noreply@localhost>";
+static SMTP_HOST_DEFAULT: &str = "localhost";
+static SMTP_PORT_DEFAULT: &str = "1025";
+static SMTP_TLS_DEFAULT: &str = "false";
+static PRICE_CHECK_INTERVAL_SECS_DEFAULT: &str = "300"; // 5 minutes, 0 disables the checks
+static NOTIFICATION_CHANNELS_DEFAULT: &str = "email";
+static NOTIFICATION_DELIVERY_INTERVAL_SECS_DEFAULT: &str = "60"; // 0 disables the delivery
+static NOTIFICATION_MAX_ATTEMPTS_DEFAULT: &str = "5";
+static NOTIFICATION_RETRY_BASE_SECS_DEFAULT: &str = "60"; // Doubles with each failed attempt
+static DIGEST_INTERVAL_SECS_DEFAULT: &str = "3600"; // 1 hour, 0 disables the digests
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub app_url: String,
     pub database_url: String,
-    pub jwt_secret: String,
+    pub jwt_keys: Arc<JwtKeys>,
     pub jwt_expires_in_secs: i32,
+    pub refresh_token_expires_in_secs: i64,
+    pub auth_user_cache_ttl_secs: u64,
+    pub login_max_failed_attempts: i64,
+    pub login_max_failed_attempts_per_ip: i64,
+    pub login_failure_window_secs: i64,
+    pub login_lockout_base_secs: i64,
+    pub login_lockout_max_secs: i64,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub password_reset_token_expires_in_secs: i64,
+    pub email_verification_token_expires_in_secs: i64,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub two_factor_issuer: String,
+    pub two_factor_challenge_expires_in_secs: i64,
+    pub auth_cookies: bool,
+    pub auth_cookie_secure: bool,
+    pub auth_cookie_same_site: SameSite,
+    pub auth_cookie_domain: Option<String>,
+    pub cors_allowed_origins: Option<Vec<String>>,
+    pub frontend_url: String,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub oidc_redirect_url: String,
+    pub oidc_auth_request_expires_in_secs: i64,
+    pub mail_transport: String,
+    pub mail_from: String,
+    pub mail_outbox_path: Option<String>,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: bool,
+    pub price_check_interval_secs: u64,
+    pub notification_channels: Vec<String>,
+    pub notification_log_path: Option<String>,
+    pub notification_delivery_interval_secs: u64,
+    pub notification_max_attempts: i32,
+    pub notification_retry_base_secs: i64,
+    pub digest_interval_secs: u64,
+}
+
+fn env_or_default<T: FromStr>(name: &str, default: &str) -> T {
+    env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .parse::<T>()
+        .unwrap_or_else(|_| panic!("{name} has an invalid value"))
+}
+
+// Each provider in OIDC_PROVIDERS is configured through OIDC_<NAME>_ISSUER,
+// OIDC_<NAME>_CLIENT_ID and optionally OIDC_<NAME>_CLIENT_SECRET and OIDC_<NAME>_SCOPES
+fn oidc_provider_from_env(name: &str) -> OidcProviderConfig {
+    if name.is_empty()
+        || name.len() > 32
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        panic!("{OIDC_PROVIDERS} contains an invalid provider name '{name}'");
+    }
+    let prefix = format!("OIDC_{}", name.to_uppercase());
+
+    OidcProviderConfig {
+        name: name.to_string(),
+        issuer: env::var(format!("{prefix}_ISSUER"))
+            .unwrap_or_else(|_| panic!("{prefix}_ISSUER must be set")),
+        client_id: env::var(format!("{prefix}_CLIENT_ID"))
+            .unwrap_or_else(|_| panic!("{prefix}_CLIENT_ID must be set")),
+        client_secret: env::var(format!("{prefix}_CLIENT_SECRET")).ok(),
+        scopes: env_or_default(&format!("{prefix}_SCOPES"), OIDC_SCOPES_DEFAULT),
+    }
 }
 
 impl Config {
     pub fn init() -> Config {
         let app_host = env::var(APP_HOST).unwrap_or_else(|_| APP_HOST_DEFAULT.to_string());
-        let app_port = env::var(APP_PORT).expect(&format!("{APP_PORT} must be set"));
+        let app_port = env::var(APP_PORT).unwrap_or_else(|_| panic!("{APP_PORT} must be set"));
         let app_url = format!("{}:{}", app_host, app_port);
-        let database_url = env::var(DATABASE_URL).expect(&format!("{DATABASE_URL} must be set"));
-        let jwt_secret = env::var(JWT_SECRET).expect(&format!("{JWT_SECRET} must be set"));
+        let database_url =
+            env::var(DATABASE_URL).unwrap_or_else(|_| panic!("{DATABASE_URL} must be set"));
+        let jwt_algorithm: String = env_or_default(JWT_ALGORITHM, JWT_ALGORITHM_DEFAULT);
+        let jwt_keys = JwtKeys::load(
+            &jwt_algorithm,
+            env::var(JWT_SECRET).ok(),
+            env::var(JWT_PRIVATE_KEY_PATH).ok(),
+            env::var(JWT_KEY_ID).ok(),
+            env::var(JWT_PUBLIC_KEYS).ok(),
+        )
+        .unwrap_or_else(|e| panic!("Failed to load JWT keys: {e}"));
         let jwt_expires_in_secs = env::var(JWT_EXPIRES_IN_SECS)
-            .expect(&format!("{JWT_EXPIRES_IN_SECS} must be set"))
+            .unwrap_or_else(|_| panic!("{JWT_EXPIRES_IN_SECS} must be set"))
             .parse::<i32>()
             .unwrap();
+        let refresh_token_expires_in_secs = env_or_default(
+            REFRESH_TOKEN_EXPIRES_IN_SECS,
+            REFRESH_TOKEN_EXPIRES_IN_SECS_DEFAULT,
+        );
+        let auth_user_cache_ttl_secs =
+            env_or_default(AUTH_USER_CACHE_TTL_SECS, AUTH_USER_CACHE_TTL_SECS_DEFAULT);
+        let login_max_failed_attempts =
+            env_or_default(LOGIN_MAX_FAILED_ATTEMPTS, LOGIN_MAX_FAILED_ATTEMPTS_DEFAULT);
+        let login_max_failed_attempts_per_ip = env_or_default(
+            LOGIN_MAX_FAILED_ATTEMPTS_PER_IP,
+            LOGIN_MAX_FAILED_ATTEMPTS_PER_IP_DEFAULT,
+        );
+        let login_failure_window_secs =
+            env_or_default(LOGIN_FAILURE_WINDOW_SECS, LOGIN_FAILURE_WINDOW_SECS_DEFAULT);
+        let login_lockout_base_secs =
+            env_or_default(LOGIN_LOCKOUT_BASE_SECS, LOGIN_LOCKOUT_BASE_SECS_DEFAULT);
+        let login_lockout_max_secs =
+            env_or_default(LOGIN_LOCKOUT_MAX_SECS, LOGIN_LOCKOUT_MAX_SECS_DEFAULT);
+        // X-Forwarded-For is only honoured on requests from these addresses
+        let trusted_proxies = env::var(TRUSTED_PROXIES)
+            .unwrap_or_default()
+            .split(',')
+            .map(|proxy| proxy.trim())
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy.parse::<IpAddr>().unwrap_or_else(|_| {
+                    panic!("{TRUSTED_PROXIES} contains an invalid address '{proxy}'")
+                })
+            })
+            .collect();
+        let password_reset_token_expires_in_secs = env_or_default(
+            PASSWORD_RESET_TOKEN_EXPIRES_IN_SECS,
+            PASSWORD_RESET_TOKEN_EXPIRES_IN_SECS_DEFAULT,
+        );
+        let email_verification_token_expires_in_secs = env_or_default(
+            EMAIL_VERIFICATION_TOKEN_EXPIRES_IN_SECS,
+            EMAIL_VERIFICATION_TOKEN_EXPIRES_IN_SECS_DEFAULT,
+        );
+        let password_policy = PasswordPolicy::load(
+            PasswordRequirements {
+                min_length: env_or_default(PASSWORD_MIN_LENGTH, PASSWORD_MIN_LENGTH_DEFAULT),
+                max_length: env_or_default(PASSWORD_MAX_LENGTH, PASSWORD_MAX_LENGTH_DEFAULT),
+                min_uppercase: env_or_default(
+                    PASSWORD_MIN_UPPERCASE,
+                    PASSWORD_MIN_UPPERCASE_DEFAULT,
+                ),
+                min_lowercase: env_or_default(
+                    PASSWORD_MIN_LOWERCASE,
+                    PASSWORD_MIN_LOWERCASE_DEFAULT,
+                ),
+                min_digits: env_or_default(PASSWORD_MIN_DIGITS, PASSWORD_MIN_DIGITS_DEFAULT),
+                min_special: env_or_default(PASSWORD_MIN_SPECIAL, PASSWORD_MIN_SPECIAL_DEFAULT),
+                blocklist_enabled: false,
+            },
+            env::var(PASSWORD_BLOCKLIST_PATH).ok(),
+        )
+        .unwrap_or_else(|e| panic!("Failed to load the password policy: {e}"));
+        let two_factor_issuer = env_or_default(TWO_FACTOR_ISSUER, TWO_FACTOR_ISSUER_DEFAULT);
+        let two_factor_challenge_expires_in_secs = env_or_default(
+            TWO_FACTOR_CHALLENGE_EXPIRES_IN_SECS,
+            TWO_FACTOR_CHALLENGE_EXPIRES_IN_SECS_DEFAULT,
+        );
+        let auth_cookies = env_or_default(AUTH_COOKIES, AUTH_COOKIES_DEFAULT);
+        let auth_cookie_secure = env_or_default(AUTH_COOKIE_SECURE, AUTH_COOKIE_SECURE_DEFAULT);
+        let auth_cookie_same_site =
+            match env_or_default::<String>(AUTH_COOKIE_SAME_SITE, AUTH_COOKIE_SAME_SITE_DEFAULT)
+                .to_lowercase()
+                .as_str()
+            {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                // Browsers drop such cookies unless they are secure
+                "none" if auth_cookie_secure => SameSite::None,
+                _ => panic!("{AUTH_COOKIE_SAME_SITE} has an invalid value"),
+            };
+        let auth_cookie_domain = env::var(AUTH_COOKIE_DOMAIN).ok();
+        let cors_allowed_origins = env::var(CORS_ALLOWED_ORIGINS).ok().map(|origins| {
+            origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect()
+        });
+        let frontend_url: String = env_or_default(FRONTEND_URL, FRONTEND_URL_DEFAULT);
+        let oidc_providers = env::var(OIDC_PROVIDERS)
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| oidc_provider_from_env(&name))
+            .collect();
+        let oidc_redirect_url = env::var(OIDC_REDIRECT_URL)
+            .unwrap_or_else(|_| format!("{}/oidc/callback", frontend_url));
+        let oidc_auth_request_expires_in_secs = env_or_default(
+            OIDC_AUTH_REQUEST_EXPIRES_IN_SECS,
+            OIDC_AUTH_REQUEST_EXPIRES_IN_SECS_DEFAULT,
+        );
+        let mail_transport = env_or_default(MAIL_TRANSPORT, MAIL_TRANSPORT_DEFAULT);
+        let mail_from = env_or_default(MAIL_FROM, MAIL_FROM_DEFAULT);
+        let mail_outbox_path = env::var(MAIL_OUTBOX_PATH).ok();
+        let smtp_host = env_or_default(SMTP_HOST, SMTP_HOST_DEFAULT);
+        let smtp_port = env_or_default(SMTP_PORT, SMTP_PORT_DEFAULT);
+        let smtp_username = env::var(SMTP_USERNAME).ok();
+        let smtp_password = env::var(SMTP_PASSWORD).ok();
+        let smtp_tls = env_or_default(SMTP_TLS, SMTP_TLS_DEFAULT);
+        let price_check_interval_secs =
+            env_or_default(PRICE_CHECK_INTERVAL_SECS, PRICE_CHECK_INTERVAL_SECS_DEFAULT);
+        let notification_channels =
+            env_or_default::<String>(NOTIFICATION_CHANNELS, NOTIFICATION_CHANNELS_DEFAULT)
+                .split(',')
+                .map(|channel| channel.trim().to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect();
+        let notification_log_path = env::var(NOTIFICATION_LOG_PATH).ok();
+        let notification_delivery_interval_secs = env_or_default(
+            NOTIFICATION_DELIVERY_INTERVAL_SECS,
+            NOTIFICATION_DELIVERY_INTERVAL_SECS_DEFAULT,
+        );
+        let notification_max_attempts =
+            env_or_default(NOTIFICATION_MAX_ATTEMPTS, NOTIFICATION_MAX_ATTEMPTS_DEFAULT);
+        let notification_retry_base_secs = env_or_default(
+            NOTIFICATION_RETRY_BASE_SECS,
+            NOTIFICATION_RETRY_BASE_SECS_DEFAULT,
+        );
+        let digest_interval_secs =
+            env_or_default(DIGEST_INTERVAL_SECS, DIGEST_INTERVAL_SECS_DEFAULT);
         Config {
             app_url,
             database_url,
-            jwt_secret,
+            jwt_keys: Arc::new(jwt_keys),
             jwt_expires_in_secs,
+            refresh_token_expires_in_secs,
+            auth_user_cache_ttl_secs,
+            login_max_failed_attempts,
+            login_max_failed_attempts_per_ip,
+            login_failure_window_secs,
+            login_lockout_base_secs,
+            login_lockout_max_secs,
+            trusted_proxies,
+            password_reset_token_expires_in_secs,
+            email_verification_token_expires_in_secs,
+            password_policy: Arc::new(password_policy),
+            two_factor_issuer,
+            two_factor_challenge_expires_in_secs,
+            auth_cookies,
+            auth_cookie_secure,
+            auth_cookie_same_site,
+            auth_cookie_domain,
+            cors_allowed_origins,
+            frontend_url,
+            oidc_providers,
+            oidc_redirect_url,
+            oidc_auth_request_expires_in_secs,
+            mail_transport,
+            mail_from,
+            mail_outbox_path,
+            smtp_host,
+            smtp_port,
+            smtp_username,
+            smtp_password,
+            smtp_tls,
+            price_check_interval_secs,
+            notification_channels,
+            notification_log_path,
+            notification_delivery_interval_secs,
+            notification_max_attempts,
+            notification_retry_base_secs,
+            digest_interval_secs,
         }
     }
 }
 
-pub fn get_cors() -> Cors {
-    Cors::permissive()
+pub fn get_cors(config: &Config) -> Cors {
+    match config.cors_allowed_origins {
+        Some(ref origins) => origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+            .allowed_headers(vec![
+                header::AUTHORIZATION,
+                header::ACCEPT,
+                header::CONTENT_TYPE,
+            ])
+            .allowed_header(CSRF_TOKEN_HEADER)
+            .allowed_header(API_KEY_HEADER)
+            .expose_headers(vec![CSRF_TOKEN_HEADER])
+            .supports_credentials()
+            .max_age(3600),
+        // Permissive CORS reflects any origin with credentials, so any site could make
+        // requests with the session cookies and read the responses
+        None if config.auth_cookies => Cors::default(),
+        None => Cors::permissive(),
+    }
 }
 
 pub fn get_openapi() -> openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
         paths(
+            account_controller::access_log,
+            account_controller::change_password,
+            account_controller::confirm_password_reset,
+            account_controller::confirm_two_factor,
+            account_controller::delete_account,
+            account_controller::disable_two_factor,
+            account_controller::enroll_two_factor,
+            account_controller::export_data,
+            account_controller::get_profile,
             account_controller::login,
+            account_controller::login_two_factor,
+            account_controller::logout,
+            account_controller::logout_all,
             account_controller::password_requirements,
             account_controller::refresh_token,
+            account_controller::request_password_reset,
+            account_controller::resend_verification_email,
+            account_controller::revoke_session,
+            account_controller::sessions,
             account_controller::signup,
             account_controller::subscriptions,
+            account_controller::update_profile,
+            account_controller::verify_email,
+            admin_controller::api_keys,
+            admin_controller::assign_role,
+            admin_controller::create_api_key,
+            admin_controller::disable_user,
+            admin_controller::enable_user,
+            admin_controller::revoke_api_key,
+            admin_controller::revoke_role,
+            admin_controller::roles,
             cart_controller::add_to_cart,
             cart_controller::get_cart,
             cart_controller::get_cart_total_price,
             category_controller::categories,
             history_controller::add_to_history,
             history_controller::get_history,
+            notification_controller::get_notifications,
+            notification_controller::mark_notification_read,
+            notification_controller::mark_all_notifications_read,
+            stream_controller::stream_prices,
+            jwks_controller::jwks,
+            oidc_controller::authorize,
+            oidc_controller::callback,
+            oidc_controller::providers,
             ping_controller::ping,
+            product_controller::add_price,
             product_controller::get_product_subscription,
             product_controller::product,
             product_controller::product_by_product_store_id,
             product_controller::products,
             product_controller::subscribe_to_product,
             product_controller::unsubscribe_from_product,
+            settings_controller::get_settings,
+            settings_controller::update_settings,
+            settings_controller::get_notification_settings,
+            settings_controller::update_notification_settings,
         ),
         components(schemas(
+            ApiKeyDTO,
             Category,
+            ChangePasswordDTO,
+            CreateApiKeyDTO,
+            CreatedApiKeyDTO,
+            DeleteAccountDTO,
+            DisableTwoFactorDTO,
+            FieldError,
             HistoryDTO,
             HistoryWithProductDTO,
+            InboxDTO,
+            InboxNotificationDTO,
             LoginDTO,
+            LoginResultDTO,
+            NewPriceDTO,
+            NotificationChannelKind,
+            NotificationOptOutDTO,
+            NotificationType,
+            OidcAuthorizationDTO,
+            OidcCallbackDTO,
             PasswordRequirements,
+            PasswordResetConfirmDTO,
+            PasswordResetRequestDTO,
+            PriceScope,
+            PriceUpdateDTO,
             Product,
             ProductStoreDTO,
             ProductDTO,
             ProductStorePriceDTO,
+            RecoveryCodesDTO,
+            Role,
+            SessionDTO,
+            SubscriptionConditionsDTO,
+            ResponseCreatedApiKey,
+            ResponseInbox,
+            ResponseLoginResult,
+            ResponseOidcAuthorization,
             ResponsePasswordRequirements,
             ResponseProduct,
             ResponseProductStore,
             ResponseProductSubscription,
+            ResponseRecoveryCodes,
             ResponseSubscriptions,
             ResponseTokens,
+            ResponseTwoFactorEnrollment,
+            ResponseUserNotificationSettings,
+            ResponseUserProfile,
+            ResponseUserSettings,
+            ResponseValidationErrors,
+            ResponseVecApiKey,
             ResponseVecCategory,
             ResponseVecHistory,
             ResponseVecProduct,
+            ResponseVecRole,
+            ResponseVecSession,
             ResponseVecShoppingCart,
+            ResponseVecString,
+            ResponseVecUserAccess,
             ResponseCartTotalPrice,
+            UserAccessDTO,
+            TwoFactorChallengeDTO,
+            TwoFactorCodeDTO,
+            TwoFactorEnrollmentDTO,
+            TwoFactorLoginDTO,
+            UpdateProfileDTO,
             UserDTO,
+            UserNotificationSettingsDTO,
+            UserProfileDTO,
             UserRefreshTokenDTO,
+            UserSettingsDTO,
             UserShoppingCartDTO,
             UserSubscribedProductDTO,
             UserTokensDTO,
@@ -112,9 +572,7 @@ pub fn get_openapi() -> openapi::OpenApi {
     )]
     struct ApiDoc;
 
-    let openapi = ApiDoc::openapi();
-
-    openapi
+    ApiDoc::openapi()
 }
 
 pub fn configure_services(cfg: &mut web::ServiceConfig) {
@@ -124,9 +582,45 @@ pub fn configure_services(cfg: &mut web::ServiceConfig) {
                 web::scope("/user")
                     .service(account_controller::signup)
                     .service(account_controller::login)
+                    .service(account_controller::login_two_factor)
                     .service(account_controller::refresh_token)
+                    .service(account_controller::logout)
+                    .service(account_controller::logout_all)
                     .service(account_controller::password_requirements)
-                    .service(account_controller::subscriptions),
+                    .service(account_controller::subscriptions)
+                    .service(account_controller::access_log)
+                    .service(account_controller::sessions)
+                    .service(account_controller::revoke_session)
+                    .service(account_controller::request_password_reset)
+                    .service(account_controller::confirm_password_reset)
+                    .service(account_controller::verify_email)
+                    .service(account_controller::resend_verification_email)
+                    .service(account_controller::get_profile)
+                    .service(account_controller::update_profile)
+                    .service(account_controller::change_password)
+                    .service(account_controller::delete_account)
+                    .service(account_controller::export_data)
+                    .service(account_controller::enroll_two_factor)
+                    .service(account_controller::confirm_two_factor)
+                    .service(account_controller::disable_two_factor)
+                    .service(settings_controller::get_settings)
+                    .service(settings_controller::update_settings)
+                    .service(settings_controller::get_notification_settings)
+                    .service(settings_controller::update_notification_settings)
+                    .service(oidc_controller::providers)
+                    .service(oidc_controller::authorize)
+                    .service(oidc_controller::callback),
+            )
+            .service(
+                web::scope("/admin")
+                    .service(admin_controller::roles)
+                    .service(admin_controller::assign_role)
+                    .service(admin_controller::revoke_role)
+                    .service(admin_controller::disable_user)
+                    .service(admin_controller::enable_user)
+                    .service(admin_controller::api_keys)
+                    .service(admin_controller::create_api_key)
+                    .service(admin_controller::revoke_api_key),
             )
             .service(
                 web::scope("/cart")
@@ -137,12 +631,18 @@ pub fn configure_services(cfg: &mut web::ServiceConfig) {
             .service(category_controller::categories)
             .service(history_controller::add_to_history)
             .service(history_controller::get_history)
+            .service(notification_controller::get_notifications)
+            .service(notification_controller::mark_notification_read)
+            .service(notification_controller::mark_all_notifications_read)
+            .service(stream_controller::stream_prices)
             .service(ping_controller::ping)
+            .service(jwks_controller::jwks)
             .service(product_controller::product)
             .service(product_controller::products)
             .service(product_controller::product_by_product_store_id)
             .service(product_controller::subscribe_to_product)
             .service(product_controller::unsubscribe_from_product)
-            .service(product_controller::get_product_subscription),
+            .service(product_controller::get_product_subscription)
+            .service(product_controller::add_price),
     );
 }
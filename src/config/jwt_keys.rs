@@ -0,0 +1,218 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use simple_asn1::{oid, ASN1Block};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+struct VerificationKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    jwk: Jwk,
+}
+
+/// Keys used to sign and verify JWTs.
+///
+/// With HS256 tokens are signed with the shared secret and carry no `kid`. With RS256 or
+/// EdDSA they are signed with the private key and carry its `kid`, and every configured
+/// public key is accepted, so an old key can stay around until its tokens expire.
+/// Tokens without a `kid` are still accepted if the shared secret is set, which allows
+/// switching from HS256 without logging everyone out.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing_key: EncodingKey,
+    signing_key_id: Option<String>,
+    verification_keys: HashMap<String, VerificationKey>,
+    secret: Option<DecodingKey>,
+}
+
+impl JwtKeys {
+    /// `public_keys` is a comma-separated list of `kid=path` pairs.
+    pub fn load(
+        algorithm: &str,
+        secret: Option<String>,
+        private_key_path: Option<String>,
+        key_id: Option<String>,
+        public_keys: Option<String>,
+    ) -> Result<JwtKeys, String> {
+        let algorithm = match algorithm {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            algorithm => return Err(format!("Unsupported JWT algorithm '{}'", algorithm)),
+        };
+
+        let mut verification_keys = HashMap::new();
+        for entry in public_keys.iter().flat_map(|keys| keys.split(',')) {
+            let (kid, path) = entry
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| format!("Public key '{}' must be given as kid=path", entry))?;
+            let pem = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let jwk = public_key_to_jwk(kid, &pem).map_err(|e| format!("{}: {}", path, e))?;
+
+            verification_keys.insert(
+                kid.to_string(),
+                VerificationKey {
+                    algorithm: jwk.common.algorithm.unwrap(),
+                    key: DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?,
+                    jwk,
+                },
+            );
+        }
+
+        let (signing_key, signing_key_id) = if algorithm == Algorithm::HS256 {
+            let secret = secret
+                .as_ref()
+                .ok_or("A JWT secret is required for HS256")?;
+            (EncodingKey::from_secret(secret.as_bytes()), None)
+        } else {
+            let path = private_key_path.ok_or("A private key is required for RS256 and EdDSA")?;
+            let key_id = key_id.ok_or("A key id is required for RS256 and EdDSA")?;
+            let pem = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let signing_key = if algorithm == Algorithm::RS256 {
+                EncodingKey::from_rsa_pem(&pem)
+            } else {
+                EncodingKey::from_ed_pem(&pem)
+            }
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+            match verification_keys.get(&key_id) {
+                Some(key) if key.algorithm == algorithm => {}
+                _ => {
+                    return Err(format!(
+                        "The {:?} public key of '{}' must be among the public keys",
+                        algorithm, key_id
+                    ))
+                }
+            }
+
+            (signing_key, Some(key_id))
+        };
+
+        Ok(JwtKeys {
+            algorithm,
+            signing_key,
+            signing_key_id,
+            verification_keys,
+            secret: secret.map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+        })
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_key_id.clone();
+
+        jsonwebtoken::encode(&header, claims, &self.signing_key)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<T> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let (algorithm, key) = match header.kid {
+            Some(kid) => match self.verification_keys.get(&kid) {
+                Some(key) => (key.algorithm, &key.key),
+                None => return Err(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat.into()),
+            },
+            None => match self.secret {
+                Some(ref secret) => (Algorithm::HS256, secret),
+                None => return Err(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat.into()),
+            },
+        };
+
+        jsonwebtoken::decode::<T>(token, key, &Validation::new(algorithm)).map(|data| data.claims)
+    }
+
+    /// The public keys in JWK format, for services that verify our tokens
+    pub fn jwks(&self) -> JwkSet {
+        let mut keys = self
+            .verification_keys
+            .values()
+            .map(|key| key.jwk.clone())
+            .collect::<Vec<Jwk>>();
+        keys.sort_by(|a, b| a.common.key_id.cmp(&b.common.key_id));
+
+        JwkSet { keys }
+    }
+}
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtKeys")
+            .field("algorithm", &self.algorithm)
+            .field("signing_key_id", &self.signing_key_id)
+            .field("verification_keys", &self.verification_keys.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+// Reads an RSA or Ed25519 public key in the PEM encoded SubjectPublicKeyInfo format,
+// which is what `openssl pkey -pubout` produces
+fn public_key_to_jwk(kid: &str, pem: &[u8]) -> Result<Jwk, String> {
+    let pem = pem::parse(pem).map_err(|e| e.to_string())?;
+    if pem.tag != "PUBLIC KEY" {
+        return Err(format!("Expected a PUBLIC KEY, found {}", pem.tag));
+    }
+
+    let (key_oid, key) = match simple_asn1::from_der(&pem.contents).as_deref() {
+        Ok([ASN1Block::Sequence(_, spki)]) => match spki.as_slice() {
+            [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, key)] => {
+                match algorithm.first() {
+                    Some(ASN1Block::ObjectIdentifier(_, key_oid)) => (key_oid.clone(), key.clone()),
+                    _ => return Err("Malformed public key".to_string()),
+                }
+            }
+            _ => return Err("Malformed public key".to_string()),
+        },
+        _ => return Err("Malformed public key".to_string()),
+    };
+
+    let (algorithm, parameters) = if key_oid == oid!(1, 2, 840, 113549, 1, 1, 1) {
+        let (n, e) = match simple_asn1::from_der(&key).as_deref() {
+            Ok([ASN1Block::Sequence(_, components)]) => match components.as_slice() {
+                [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => {
+                    (n.to_bytes_be().1, e.to_bytes_be().1)
+                }
+                _ => return Err("Malformed RSA public key".to_string()),
+            },
+            _ => return Err("Malformed RSA public key".to_string()),
+        };
+
+        (
+            Algorithm::RS256,
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(n),
+                e: URL_SAFE_NO_PAD.encode(e),
+            }),
+        )
+    } else if key_oid == oid!(1, 3, 101, 112) {
+        (
+            Algorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key),
+            }),
+        )
+    } else {
+        return Err("Only RSA and Ed25519 public keys are supported".to_string());
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
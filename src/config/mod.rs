@@ -1,2 +1,4 @@
 pub mod app;
 pub mod db;
+pub mod jwt_keys;
+pub mod password_policy;
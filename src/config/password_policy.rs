@@ -0,0 +1,96 @@
+use crate::models::user::PasswordRequirements;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+
+// Shorter logins would match too many unrelated passwords
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+
+/// The password rules in effect: the character requirements, an optional blocklist of
+/// common or breached passwords and a check against the user's own login and email.
+pub struct PasswordPolicy {
+    requirements: PasswordRequirements,
+    blocklist: Option<HashSet<String>>,
+}
+
+impl PasswordPolicy {
+    /// `blocklist_path` points to a file with one password per line. Passwords are
+    /// compared case-insensitively.
+    pub fn load(
+        requirements: PasswordRequirements,
+        blocklist_path: Option<String>,
+    ) -> Result<PasswordPolicy, String> {
+        if requirements.min_length > requirements.max_length {
+            return Err("The minimum password length is above the maximum".to_string());
+        }
+        let min_classes = requirements.min_uppercase
+            + requirements.min_lowercase
+            + requirements.min_digits
+            + requirements.min_special;
+        if min_classes > requirements.max_length {
+            return Err("The required characters don't fit in the maximum length".to_string());
+        }
+
+        let blocklist = match blocklist_path {
+            Some(path) => {
+                let contents =
+                    fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+                Some(
+                    // Leaked password lists aren't always valid UTF-8
+                    String::from_utf8_lossy(&contents)
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty())
+                        .map(|line| line.to_lowercase())
+                        .collect::<HashSet<String>>(),
+                )
+            }
+            None => None,
+        };
+
+        Ok(PasswordPolicy {
+            requirements: PasswordRequirements {
+                blocklist_enabled: blocklist.is_some(),
+                ..requirements
+            },
+            blocklist,
+        })
+    }
+
+    pub fn requirements(&self) -> PasswordRequirements {
+        self.requirements.clone()
+    }
+
+    pub fn validate(&self, password: &str, login: &str, email: &str) -> Result<(), String> {
+        self.requirements.validate(password)?;
+
+        let lowercase_password = password.to_lowercase();
+
+        if let Some(ref blocklist) = self.blocklist {
+            if blocklist.contains(&lowercase_password) {
+                return Err("Password is too common, please choose another one".to_string());
+            }
+        }
+
+        let email_name = email.split('@').next().unwrap_or_default();
+        let contains_personal_info = [login, email_name]
+            .iter()
+            .map(|value| value.to_lowercase())
+            .filter(|value| value.chars().count() >= MIN_PERSONAL_INFO_LENGTH)
+            .any(|value| lowercase_password.contains(&value));
+        if contains_personal_info {
+            return Err("Password must not contain your login or email".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PasswordPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordPolicy")
+            .field("requirements", &self.requirements)
+            .field("blocklist_size", &self.blocklist.as_ref().map(HashSet::len))
+            .finish()
+    }
+}
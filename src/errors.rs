@@ -1,4 +1,5 @@
 use crate::models::response::ResponseBody;
+use crate::models::validation::FieldError;
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use deadpool_diesel::PoolError;
@@ -6,6 +7,7 @@ use derive_more::{Display, From};
 
 #[derive(Display, From, Debug)]
 pub enum MyError {
+    #[allow(dead_code)]
     NotFound,
     PoolError(PoolError),
     UnknownError,
@@ -27,6 +29,7 @@ impl ResponseError for MyError {
 pub struct ServiceError {
     pub http_status: StatusCode,
     pub body: ResponseBody<String>,
+    pub field_errors: Option<Vec<FieldError>>,
 }
 
 impl ServiceError {
@@ -37,10 +40,23 @@ impl ServiceError {
                 status: "error".to_string(),
                 data: message,
             },
+            field_errors: None,
+        }
+    }
+
+    /// A 400 response listing the invalid fields in place of the message
+    pub fn validation(field_errors: Vec<FieldError>) -> ServiceError {
+        ServiceError {
+            field_errors: Some(field_errors),
+            ..ServiceError::new(StatusCode::BAD_REQUEST, "Validation failed".to_string())
         }
     }
 
     pub fn response(&self) -> HttpResponse {
-        HttpResponse::build(self.http_status).json(&self.body)
+        match self.field_errors {
+            Some(ref field_errors) => HttpResponse::build(self.http_status)
+                .json(ResponseBody::new(&self.body.status, field_errors)),
+            None => HttpResponse::build(self.http_status).json(&self.body),
+        }
     }
 }
@@ -0,0 +1,45 @@
+use crate::config::app::Config;
+use crate::mailer::{Email, Mailer};
+use log::info;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Writes emails to the log instead of sending them, and appends them to
+/// `MAIL_OUTBOX_PATH` when it is set.
+pub struct LogMailer {
+    outbox: Option<Mutex<std::fs::File>>,
+}
+
+impl LogMailer {
+    pub fn new(config: &Config) -> LogMailer {
+        let outbox = config.mail_outbox_path.as_ref().map(|path| {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("Failed to open mail outbox file");
+            Mutex::new(file)
+        });
+
+        LogMailer { outbox }
+    }
+}
+
+impl Mailer for LogMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        info!("Mail to {} with subject '{}'", email.to, email.subject);
+
+        if let Some(ref outbox) = self.outbox {
+            let mut file = outbox.lock().unwrap();
+            writeln!(
+                file,
+                "To: {}\nSubject: {}\n\n{}\n\n---\n",
+                email.to, email.subject, email.body
+            )
+            .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
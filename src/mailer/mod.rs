@@ -0,0 +1,40 @@
+pub mod log_mailer;
+pub mod smtp_mailer;
+
+use crate::config::app::Config;
+use crate::mailer::log_mailer::LogMailer;
+use crate::mailer::smtp_mailer::SmtpMailer;
+use actix_web::web;
+use log::error;
+use std::sync::Arc;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends plain text emails. Implementations are blocking, so call them through `web::block`.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), String>;
+}
+
+pub fn get_mailer(config: &Config) -> Arc<dyn Mailer> {
+    match config.mail_transport.as_str() {
+        "smtp" => Arc::new(SmtpMailer::new(config)),
+        "log" => Arc::new(LogMailer::new(config)),
+        transport => panic!("Unknown mail transport '{}'", transport),
+    }
+}
+
+/// Sends the email on the blocking thread pool without waiting for the result,
+/// so response times don't depend on the mail server. Failures are only logged.
+pub fn send_in_background(mailer: web::Data<dyn Mailer>, email: Email) {
+    actix_web::rt::spawn(async move {
+        match web::block(move || mailer.send(&email)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to send email: {}", e),
+            Err(e) => error!("Failed to send email: {}", e),
+        }
+    });
+}
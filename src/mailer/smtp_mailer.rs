@@ -0,0 +1,53 @@
+use crate::config::app::Config;
+use crate::mailer::{Email, Mailer};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &Config) -> SmtpMailer {
+        let mut builder = if config.smtp_tls {
+            SmtpTransport::relay(&config.smtp_host).expect("Failed to create SMTP transport")
+        } else {
+            // Plain connection, e.g. to a local SMTP catcher in development
+            SmtpTransport::builder_dangerous(&config.smtp_host)
+        }
+        .port(config.smtp_port);
+
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        SmtpMailer {
+            transport: builder.build(),
+            from: config
+                .mail_from
+                .parse()
+                .expect("MAIL_FROM is not a valid mailbox"),
+        }
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email
+                .to
+                .parse()
+                .map_err(|e| format!("Invalid recipient: {e}"))?)
+            .subject(&email.subject)
+            .body(email.body.clone())
+            .map_err(|e| e.to_string())?;
+
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
@@ -1,12 +1,21 @@
 mod api;
 mod config;
 mod errors;
+mod mailer;
 mod middlewares;
 mod models;
+mod notifications;
+mod oidc;
+mod price_updates;
 mod schema;
 mod services;
+mod workers;
 
 use crate::config::app::Config;
+use crate::middlewares::jwt_middleware::UserCache;
+use crate::oidc::OidcClient;
+use crate::price_updates::connection::ListenerConfig;
+use crate::price_updates::PriceUpdates;
 use actix_web::{middleware, web, App, HttpServer};
 use dotenvy::dotenv;
 use log::info;
@@ -19,6 +28,9 @@ async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let config = Config::init();
+    // The listener connects without libpq, so check it can before anything is started
+    let listener_config = ListenerConfig::from_database_url(&config.database_url)
+        .unwrap_or_else(|e| panic!("Can't listen for price updates on DATABASE_URL: {}", e));
 
     let pool = config::db::get_connection_pool(&config.database_url).await;
     config::db::run_migrations(pool.clone()).await;
@@ -27,15 +39,30 @@ async fn main() -> std::io::Result<()> {
     info!("Starting server at http://{}", app_url);
 
     let openapi = config::app::get_openapi();
+    let mailer = mailer::get_mailer(&config);
+    let user_cache = web::Data::new(UserCache::new(config.auth_user_cache_ttl_secs));
+    let oidc_client = web::Data::new(OidcClient::new(&config));
+
+    workers::price_drops::start(pool.clone(), &config);
+    let notification_channels = notifications::get_channels(&config, mailer.clone());
+    workers::notifications::start(pool.clone(), &config, notification_channels.clone());
+    workers::digests::start(pool.clone(), &config, notification_channels);
+    let price_updates = PriceUpdates::new();
+    workers::price_updates::start(listener_config, price_updates.clone());
+    let price_updates = web::Data::new(price_updates);
 
     HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::new(
                 "%{r}a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %Dms",
             ))
-            .wrap(config::app::get_cors())
+            .wrap(config::app::get_cors(&config))
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(user_cache.clone())
+            .app_data(oidc_client.clone())
+            .app_data(price_updates.clone())
             .service(
                 SwaggerUi::new("/api/swagger-ui/{_:.*}")
                     .url("/api/api-docs/openapi.json", openapi.clone()),
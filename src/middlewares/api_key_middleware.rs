@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+
+use crate::errors::MyError;
+use crate::middlewares::jwt_middleware::ErrorResponse;
+use crate::models::api_key::ApiKey;
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::{dev::Payload, Error as ActixWebError};
+use actix_web::{web, FromRequest, HttpRequest};
+use deadpool_diesel::postgres::Pool;
+
+pub static API_KEY_HEADER: &str = "X-Api-Key";
+
+fn error_response(message: String) -> ErrorResponse {
+    ErrorResponse {
+        status: "error".to_string(),
+        message,
+    }
+}
+
+/// A machine client authenticated with an API key
+#[derive(Debug, Clone)]
+pub struct ApiKeyPrincipal {
+    pub key_id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl ApiKeyPrincipal {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+impl FromRequest for ApiKeyPrincipal {
+    type Error = ActixWebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<Pool>>().unwrap().clone();
+
+        let key = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_string());
+
+        Box::pin(async move {
+            let key = key.ok_or_else(|| {
+                ErrorUnauthorized(error_response(format!(
+                    "The {} header is required",
+                    API_KEY_HEADER
+                )))
+            })?;
+
+            let conn = pool.get().await.map_err(MyError::PoolError)?;
+            let api_key = conn
+                .interact(move |conn| ApiKey::authenticate(conn, &key))
+                .await
+                .unwrap();
+
+            match api_key {
+                Ok(Some(api_key)) => Ok(ApiKeyPrincipal {
+                    key_id: api_key.id,
+                    name: api_key.name,
+                    scopes: api_key.scopes,
+                }),
+                _ => Err(ErrorUnauthorized(error_response(
+                    "Invalid API key".to_string(),
+                ))),
+            }
+        })
+    }
+}
+
+/// A scope that can be required by [`RequireScope`].
+pub trait ScopeName {
+    const NAME: &'static str;
+}
+
+pub struct PricesWrite;
+
+impl ScopeName for PricesWrite {
+    const NAME: &'static str = "prices:write";
+}
+
+/// Authenticates the request with an API key and rejects it with 403 unless the key
+/// was granted the scope `S`.
+pub struct RequireScope<S: ScopeName> {
+    pub principal: ApiKeyPrincipal,
+    _scope: PhantomData<S>,
+}
+
+impl<S: ScopeName> Deref for RequireScope<S> {
+    type Target = ApiKeyPrincipal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.principal
+    }
+}
+
+impl<S: ScopeName + 'static> FromRequest for RequireScope<S> {
+    type Error = ActixWebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let principal = ApiKeyPrincipal::from_request(req, payload);
+
+        Box::pin(async move {
+            let principal = principal.await?;
+
+            if !principal.has_scope(S::NAME) {
+                return Err(ErrorForbidden(error_response(format!(
+                    "The '{}' scope is required",
+                    S::NAME
+                ))));
+            }
+
+            Ok(RequireScope {
+                principal,
+                _scope: PhantomData,
+            })
+        })
+    }
+}
@@ -1,17 +1,30 @@
 use core::fmt;
-use std::future::{ready, Ready};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use crate::config::app::Config;
+use crate::errors::MyError;
+use crate::middlewares::session_cookies::{self, ACCESS_TOKEN_COOKIE};
+use crate::models::user::User;
+use crate::models::user_tokens::UserToken;
 use actix_web::error::ErrorUnauthorized;
 use actix_web::{dev::Payload, Error as ActixWebError};
-use actix_web::{http, web, FromRequest, HttpRequest};
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use actix_web::{http, web, FromRequest, HttpMessage, HttpRequest};
+use deadpool_diesel::postgres::Pool;
+use diesel::OptionalExtension;
 use serde::{Deserialize, Serialize};
 
+// Expired entries are only dropped once the cache grows this big
+const USER_CACHE_PRUNE_SIZE: usize = 1024;
+
 #[derive(Debug, Serialize)]
-struct ErrorResponse {
-    status: String,
-    message: String,
+pub struct ErrorResponse {
+    pub status: String,
+    pub message: String,
 }
 
 impl fmt::Display for ErrorResponse {
@@ -20,6 +33,14 @@ impl fmt::Display for ErrorResponse {
     }
 }
 
+fn unauthorized(message: &str) -> ActixWebError {
+    let json_error = ErrorResponse {
+        status: "error".to_string(),
+        message: message.to_string(),
+    };
+    ErrorUnauthorized(json_error)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TokenClaims {
     pub sub: String,
@@ -28,42 +49,185 @@ pub struct TokenClaims {
     pub nickname: Option<String>,
     pub login: String,
     pub roles: Vec<String>,
+    pub ver: i32,
+    pub sid: String,
+}
+
+impl TokenClaims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role || r == "admin")
+    }
 }
 
 impl FromRequest for TokenClaims {
     type Error = ActixWebError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth_user = AuthUser::from_request(req, payload);
+
+        Box::pin(async move { Ok(auth_user.await?.claims) })
+    }
+}
+
+/// Users recently loaded by [`AuthUser`], with the ids of their sessions, so that every
+/// request doesn't hit the database. Changes to a user are picked up once the entry
+/// expires, or right away where the entry is invalidated.
+pub struct UserCache {
+    ttl: Duration,
+    users: Mutex<HashMap<i32, CachedUser>>,
+}
+
+#[derive(Clone)]
+struct CachedUser {
+    cached_at: Instant,
+    user: User,
+    session_ids: HashSet<String>,
+}
+
+impl UserCache {
+    pub fn new(ttl_secs: u64) -> UserCache {
+        UserCache {
+            ttl: Duration::from_secs(ttl_secs),
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, user_id: i32) -> Option<CachedUser> {
+        let users = self.users.lock().unwrap();
+
+        users
+            .get(&user_id)
+            .filter(|cached| cached.cached_at.elapsed() < self.ttl)
+            .cloned()
+    }
+
+    fn insert(&self, cached: CachedUser) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut users = self.users.lock().unwrap();
+        if users.len() >= USER_CACHE_PRUNE_SIZE {
+            users.retain(|_, cached| cached.cached_at.elapsed() < self.ttl);
+        }
+        users.insert(cached.user.id, cached);
+    }
+
+    pub fn invalidate(&self, user_id: i32) {
+        self.users.lock().unwrap().remove(&user_id);
+    }
+}
+
+/// The user the access token was issued to, looked up by the `sub` claim. The token is
+/// read from the `Authorization` header or, in cookie mode, from the access token cookie. Tokens of
+/// deleted or disabled users, tokens issued before the last logout-all, and tokens of
+/// revoked sessions are rejected.
+/// The user is loaded once per request, and shared with every extractor that needs it.
+#[derive(Clone)]
+pub struct AuthUser {
+    pub user: User,
+    pub claims: TokenClaims,
+}
+
+impl Deref for AuthUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = ActixWebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let config = req.app_data::<web::Data<Config>>().unwrap();
+        if let Some(auth_user) = req.extensions().get::<AuthUser>() {
+            let auth_user = auth_user.clone();
+            return Box::pin(async move { Ok(auth_user) });
+        }
 
-        let token = req
+        let req = req.clone();
+        let config = req.app_data::<web::Data<Config>>().unwrap().clone();
+        let pool = req.app_data::<web::Data<Pool>>().unwrap().clone();
+        let user_cache = req.app_data::<web::Data<UserCache>>().unwrap().clone();
+
+        let header_token = req
             .headers()
             .get(http::header::AUTHORIZATION)
             .map(|h| h.to_str().unwrap().to_string());
+        let cookie_token = session_cookies::session_cookie(&req, ACCESS_TOKEN_COOKIE, &config);
 
-        if token.is_none() {
-            let json_error = ErrorResponse {
-                status: "error".to_string(),
-                message: "You are not logged in, please login first".to_string(),
+        Box::pin(async move {
+            let token = match (header_token, cookie_token) {
+                (Some(token), _) => token,
+                (None, Some(token)) => {
+                    session_cookies::verify_csrf(&req)?;
+                    token
+                }
+                (None, None) => {
+                    return Err(unauthorized("You are not logged in, please login first"))
+                }
             };
-            return ready(Err(ErrorUnauthorized(json_error)));
-        }
 
-        let claims = match decode::<TokenClaims>(
-            &token.unwrap(),
-            &DecodingKey::from_secret(config.jwt_secret.as_ref()),
-            &Validation::default(),
-        ) {
-            Ok(c) => c.claims,
-            Err(_) => {
-                let json_error = ErrorResponse {
-                    status: "error".to_string(),
-                    message: "Invalid access token".to_string(),
-                };
-                return ready(Err(ErrorUnauthorized(json_error)));
+            let claims = match config.jwt_keys.decode::<TokenClaims>(&token) {
+                Ok(claims) => claims,
+                Err(_) => return Err(unauthorized("Invalid access token")),
+            };
+
+            let user_id = claims
+                .sub
+                .parse::<i32>()
+                .map_err(|_| unauthorized("Invalid access token"))?;
+
+            // A session started after the user was cached isn't in the entry yet
+            let cached = match user_cache
+                .get(user_id)
+                .filter(|cached| cached.session_ids.contains(&claims.sid))
+            {
+                Some(cached) => cached,
+                None => {
+                    let conn = pool.get().await.map_err(MyError::PoolError)?;
+                    let cached = conn
+                        .interact(move |conn| {
+                            let Some(user) = User::find_user_by_id(conn, user_id).optional()?
+                            else {
+                                return Ok(None);
+                            };
+                            let session_ids = UserToken::session_ids(conn, user_id)?;
+
+                            Ok::<_, diesel::result::Error>(Some(CachedUser {
+                                cached_at: Instant::now(),
+                                user,
+                                session_ids: session_ids.into_iter().collect(),
+                            }))
+                        })
+                        .await
+                        .unwrap()
+                        .map_err(|_| MyError::UnknownError)?
+                        .ok_or_else(|| unauthorized("Account no longer exists"))?;
+
+                    user_cache.insert(cached.clone());
+                    cached
+                }
+            };
+            let user = cached.user;
+
+            if user.disabled {
+                return Err(unauthorized("Account is disabled"));
             }
-        };
+            // Tokens issued before the last logout-all carry an outdated version
+            if user.token_version != claims.ver {
+                return Err(unauthorized("Access token has been revoked"));
+            }
+            // The session was logged out or revoked from another device
+            if !cached.session_ids.contains(&claims.sid) {
+                return Err(unauthorized("Access token has been revoked"));
+            }
+
+            let auth_user = AuthUser { user, claims };
+            req.extensions_mut().insert(auth_user.clone());
 
-        ready(Ok(claims))
+            Ok(auth_user)
+        })
     }
 }
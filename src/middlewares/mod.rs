@@ -1 +1,4 @@
+pub mod api_key_middleware;
 pub mod jwt_middleware;
+pub mod role_middleware;
+pub mod session_cookies;
@@ -0,0 +1,67 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+
+use crate::middlewares::jwt_middleware::{AuthUser, ErrorResponse};
+use actix_web::error::ErrorForbidden;
+use actix_web::{dev::Payload, Error as ActixWebError};
+use actix_web::{FromRequest, HttpRequest};
+
+/// A role that can be required by [`RequireRole`].
+pub trait RoleName {
+    const NAME: &'static str;
+}
+
+pub struct Admin;
+
+impl RoleName for Admin {
+    const NAME: &'static str = "admin";
+}
+
+pub struct Moderator;
+
+impl RoleName for Moderator {
+    const NAME: &'static str = "moderator";
+}
+
+/// Extracts the authenticated user and rejects the request with 403 unless the user has the
+/// role `R` (admins pass every role check). Roles are read from the token, so granting or
+/// revoking a role takes effect on the next token refresh.
+pub struct RequireRole<R: RoleName> {
+    pub auth_user: AuthUser,
+    _role: PhantomData<R>,
+}
+
+impl<R: RoleName> Deref for RequireRole<R> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.auth_user
+    }
+}
+
+impl<R: RoleName + 'static> FromRequest for RequireRole<R> {
+    type Error = ActixWebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth_user = AuthUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let auth_user = auth_user.await?;
+
+            if !auth_user.claims.has_role(R::NAME) {
+                let json_error = ErrorResponse {
+                    status: "error".to_string(),
+                    message: format!("The '{}' role is required", R::NAME),
+                };
+                return Err(ErrorForbidden(json_error));
+            }
+
+            Ok(RequireRole {
+                auth_user,
+                _role: PhantomData,
+            })
+        })
+    }
+}
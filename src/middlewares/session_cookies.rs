@@ -0,0 +1,224 @@
+use crate::config::app::Config;
+use crate::middlewares::jwt_middleware::ErrorResponse;
+use crate::models::response::ResponseBody;
+use crate::models::user::LoginResultDTO;
+use crate::models::user_tokens::{UserRefreshTokenDTO, UserTokensDTO};
+use actix_web::cookie::{time, Cookie};
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::{web, Error as ActixWebError, HttpRequest, HttpResponse, HttpResponseBuilder};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+pub static ACCESS_TOKEN_COOKIE: &str = "access_token";
+pub static REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+pub static CSRF_TOKEN_COOKIE: &str = "csrf_token";
+pub static CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+pub static OIDC_STATE_COOKIE: &str = "oidc_state";
+
+// The refresh token is only needed by the refresh and logout routes
+static ACCESS_TOKEN_PATH: &str = "/api";
+static REFRESH_TOKEN_PATH: &str = "/api/user";
+static CSRF_TOKEN_PATH: &str = "/";
+static OIDC_STATE_PATH: &str = "/api/user/oidc";
+
+fn build_cookie(
+    name: &'static str,
+    value: String,
+    path: &'static str,
+    http_only: bool,
+    max_age_secs: i64,
+    config: &Config,
+) -> Cookie<'static> {
+    let mut builder = Cookie::build(name, value)
+        .path(path)
+        .http_only(http_only)
+        .secure(config.auth_cookie_secure)
+        .same_site(config.auth_cookie_same_site)
+        .max_age(time::Duration::seconds(max_age_secs));
+    if let Some(ref domain) = config.auth_cookie_domain {
+        builder = builder.domain(domain.clone());
+    }
+
+    builder.finish()
+}
+
+/// Responds with the tokens in the body or, in cookie mode, sets them as `HttpOnly`
+/// cookies instead, along with a new CSRF token that the client has to send back in
+/// the `X-CSRF-Token` header.
+pub fn tokens_response(tokens: UserTokensDTO, config: &Config) -> HttpResponse {
+    if !config.auth_cookies {
+        return HttpResponse::Ok().json(ResponseBody::new("success", tokens));
+    }
+
+    let mut response = HttpResponse::Ok();
+    set_session_cookies(&mut response, tokens, config);
+    response.json(ResponseBody::new("success", ""))
+}
+
+/// Like [`tokens_response`], but a two-factor challenge is always returned in the body
+pub fn login_response(login_result: LoginResultDTO, config: &Config) -> HttpResponse {
+    match login_result {
+        LoginResultDTO::Tokens(tokens) => tokens_response(tokens, config),
+        challenge => HttpResponse::Ok().json(ResponseBody::new("success", challenge)),
+    }
+}
+
+fn set_session_cookies(response: &mut HttpResponseBuilder, tokens: UserTokensDTO, config: &Config) {
+    let csrf_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    response
+        .cookie(build_cookie(
+            ACCESS_TOKEN_COOKIE,
+            tokens.access_token,
+            ACCESS_TOKEN_PATH,
+            true,
+            config.jwt_expires_in_secs as i64,
+            config,
+        ))
+        .cookie(build_cookie(
+            REFRESH_TOKEN_COOKIE,
+            tokens.refresh_token,
+            REFRESH_TOKEN_PATH,
+            true,
+            config.refresh_token_expires_in_secs,
+            config,
+        ))
+        .cookie(build_cookie(
+            CSRF_TOKEN_COOKIE,
+            csrf_token.clone(),
+            CSRF_TOKEN_PATH,
+            false,
+            config.refresh_token_expires_in_secs,
+            config,
+        ))
+        // A frontend on another origin can't read the cookie
+        .insert_header((CSRF_TOKEN_HEADER, csrf_token));
+}
+
+/// Expires the session cookies, if cookie mode is on
+pub fn clear_session_cookies(response: &mut HttpResponseBuilder, config: &Config) {
+    if !config.auth_cookies {
+        return;
+    }
+
+    for (name, path, http_only) in [
+        (ACCESS_TOKEN_COOKIE, ACCESS_TOKEN_PATH, true),
+        (REFRESH_TOKEN_COOKIE, REFRESH_TOKEN_PATH, true),
+        (CSRF_TOKEN_COOKIE, CSRF_TOKEN_PATH, false),
+    ] {
+        response.cookie(build_cookie(
+            name,
+            String::new(),
+            path,
+            http_only,
+            0,
+            config,
+        ));
+    }
+}
+
+/// Remembers the state of an OIDC login in the browser that started it, whatever the
+/// cookie mode, so the callback can't be completed from another one
+pub fn oidc_state_cookie(state: String, config: &Config) -> Cookie<'static> {
+    build_cookie(
+        OIDC_STATE_COOKIE,
+        state,
+        OIDC_STATE_PATH,
+        true,
+        config.oidc_auth_request_expires_in_secs,
+        config,
+    )
+}
+
+/// Expires the OIDC state cookie once the login is done
+pub fn clear_oidc_state_cookie(config: &Config) -> Cookie<'static> {
+    build_cookie(
+        OIDC_STATE_COOKIE,
+        String::new(),
+        OIDC_STATE_PATH,
+        true,
+        0,
+        config,
+    )
+}
+
+/// Otherwise an attacker could have the victim complete a login the attacker started,
+/// signing them in to the attacker's account
+pub fn verify_oidc_state(req: &HttpRequest, state: &str) -> Result<(), ActixWebError> {
+    match req.cookie(OIDC_STATE_COOKIE) {
+        Some(cookie) if !state.is_empty() && constant_time_eq(cookie.value(), state) => Ok(()),
+        _ => Err(ErrorUnauthorized(ErrorResponse {
+            status: "error".to_string(),
+            message: "Login was not started in this browser".to_string(),
+        })),
+    }
+}
+
+/// Reads a session cookie, if cookie mode is on
+pub fn session_cookie(req: &HttpRequest, name: &str, config: &Config) -> Option<String> {
+    if !config.auth_cookies {
+        return None;
+    }
+
+    req.cookie(name).map(|cookie| cookie.value().to_string())
+}
+
+/// The refresh token from the request body or, in cookie mode, from its cookie
+pub fn refresh_token_from_request(
+    body: Option<web::Json<UserRefreshTokenDTO>>,
+    req: &HttpRequest,
+    config: &Config,
+) -> Result<UserRefreshTokenDTO, ActixWebError> {
+    if let Some(body) = body {
+        return Ok(body.into_inner());
+    }
+
+    match session_cookie(req, REFRESH_TOKEN_COOKIE, config) {
+        Some(refresh_token) => {
+            verify_csrf(req)?;
+            Ok(UserRefreshTokenDTO { refresh_token })
+        }
+        None => Err(ErrorUnauthorized(ErrorResponse {
+            status: "error".to_string(),
+            message: "Refresh token is missing".to_string(),
+        })),
+    }
+}
+
+/// Requests authenticated with cookies are sent by the browser from any site, so
+/// state-changing ones must also carry the CSRF token, which only our frontend can read.
+pub fn verify_csrf(req: &HttpRequest) -> Result<(), ActixWebError> {
+    if req.method().is_safe() {
+        return Ok(());
+    }
+
+    let cookie = req.cookie(CSRF_TOKEN_COOKIE);
+    let header = req
+        .headers()
+        .get(CSRF_TOKEN_HEADER)
+        .and_then(|header| header.to_str().ok());
+
+    match (cookie, header) {
+        (Some(cookie), Some(header))
+            if !header.is_empty() && constant_time_eq(cookie.value(), header) =>
+        {
+            Ok(())
+        }
+        _ => Err(ErrorForbidden(ErrorResponse {
+            status: "error".to_string(),
+            message: "CSRF token is missing or invalid".to_string(),
+        })),
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (x, y)| difference | (x ^ y))
+            == 0
+}
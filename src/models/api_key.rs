@@ -0,0 +1,156 @@
+use crate::models::user_tokens::UserToken;
+use crate::schema::api_keys::{self, dsl::*};
+use chrono::{NaiveDateTime, Utc};
+use diesel::insert_into;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Scopes an API key can be granted. The catalog is public, so reading it needs none.
+pub const API_KEY_SCOPES: &[&str] = &["prices:write"];
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_by: i32,
+    pub created_date: NaiveDateTime,
+    pub last_used_date: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKeyInsertable {
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_by: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyDTO {
+    pub id: i32,
+    pub name: String,
+    /// The first characters of the key, to tell keys apart
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_by: i32,
+    pub created_date: NaiveDateTime,
+    pub last_used_date: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyDTO {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKeyDTO {
+    pub id: i32,
+    pub name: String,
+    /// The key itself; it is only shown once
+    pub key: String,
+    pub scopes: Vec<String>,
+}
+
+impl From<ApiKey> for ApiKeyDTO {
+    fn from(api_key: ApiKey) -> Self {
+        ApiKeyDTO {
+            id: api_key.id,
+            name: api_key.name,
+            key_prefix: api_key.key_prefix,
+            scopes: api_key.scopes,
+            created_by: api_key.created_by,
+            created_date: api_key.created_date,
+            last_used_date: api_key.last_used_date,
+        }
+    }
+}
+
+impl ApiKey {
+    /// Creates a key with the given scopes. Only its hash is stored; the plaintext is
+    /// returned to the caller once.
+    pub fn create(
+        conn: &mut PgConnection,
+        _created_by: i32,
+        api_key: CreateApiKeyDTO,
+    ) -> Result<CreatedApiKeyDTO, String> {
+        if api_key.name.trim().is_empty() || api_key.name.chars().count() > 64 {
+            return Err("Name must be between 1 and 64 characters long".to_string());
+        }
+        if api_key.scopes.is_empty() {
+            return Err("At least one scope is required".to_string());
+        }
+        if let Some(unknown) = api_key
+            .scopes
+            .iter()
+            .find(|scope| !API_KEY_SCOPES.contains(&scope.as_str()))
+        {
+            return Err(format!("Unknown scope '{}'", unknown));
+        }
+
+        let secret: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect();
+        // The prefix makes leaked keys easy to recognise, e.g. by secret scanners
+        let key = format!("pt_{}", secret);
+
+        let created = insert_into(api_keys)
+            .values(ApiKeyInsertable {
+                name: api_key.name.trim().to_string(),
+                key_prefix: key[..10].to_string(),
+                key_hash: UserToken::hash_token(&key),
+                scopes: api_key.scopes,
+                created_by: _created_by,
+            })
+            .get_result::<ApiKey>(conn)
+            .map_err(|e| e.to_string())?;
+
+        Ok(CreatedApiKeyDTO {
+            id: created.id,
+            name: created.name,
+            key,
+            scopes: created.scopes,
+        })
+    }
+
+    pub fn get_api_keys(conn: &mut PgConnection) -> QueryResult<Vec<ApiKeyDTO>> {
+        Ok(api_keys
+            .select(ApiKey::as_select())
+            .order_by(api_keys::id)
+            .get_results(conn)?
+            .into_iter()
+            .map(ApiKeyDTO::from)
+            .collect::<Vec<ApiKeyDTO>>())
+    }
+
+    pub fn revoke(conn: &mut PgConnection, _id: i32) -> QueryResult<usize> {
+        diesel::delete(api_keys.filter(api_keys::id.eq(_id))).execute(conn)
+    }
+
+    pub fn count_created_by(conn: &mut PgConnection, _created_by: i32) -> QueryResult<i64> {
+        api_keys
+            .filter(created_by.eq(_created_by))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Looks the key up and marks it as used
+    pub fn authenticate(conn: &mut PgConnection, key: &str) -> QueryResult<Option<ApiKey>> {
+        diesel::update(api_keys.filter(key_hash.eq(UserToken::hash_token(key))))
+            .set(last_used_date.eq(Utc::now().naive_utc()))
+            .returning(ApiKey::as_returning())
+            .get_result(conn)
+            .optional()
+    }
+}
@@ -1,6 +1,18 @@
+pub mod api_key;
 pub mod category;
+pub mod notification;
+pub mod oidc_auth_request;
 pub mod product;
 pub mod response;
+pub mod role;
 pub mod store;
+pub mod two_factor;
 pub mod user;
+pub mod user_access;
+pub mod user_data;
+pub mod user_identity;
+pub mod user_notification_settings;
+pub mod user_one_time_token;
+pub mod user_settings;
 pub mod user_tokens;
+pub mod validation;
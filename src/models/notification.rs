@@ -0,0 +1,554 @@
+use crate::models::product::{LatestPrice, Product};
+use crate::models::user::UserSubscribedProduct;
+use crate::models::user_notification_settings::NotificationType;
+use crate::models::validation::{FieldError, Validate};
+use crate::schema::delivered_notifications;
+use crate::schema::products;
+use crate::schema::stores;
+use crate::schema::user_subscribed_products::{self, dsl::*};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Varchar};
+use diesel::{insert_into, select, update};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use utoipa::{IntoParams, ToSchema};
+
+// Held for the duration of a price check, so only one instance records each drop
+const PRICE_CHECK_LOCK_KEY: i64 = 0x7072_6963_6564_726f;
+
+sql_function!(fn pg_try_advisory_xact_lock(key: BigInt) -> Bool);
+sql_function!(fn array_append(array: Array<Varchar>, element: Varchar) -> Array<Varchar>);
+
+pub const INBOX_PAGE_SIZE_DEFAULT: i64 = 20;
+pub const INBOX_PAGE_SIZE_MAX: i64 = 100;
+// Keeps the offset well within an i64
+pub const INBOX_PAGE_MAX: i64 = 100_000;
+
+/// A notification to deliver, with the subscription it is about
+#[derive(Queryable)]
+pub struct PendingNotification {
+    pub id: i32,
+    pub old_price: Option<f32>,
+    pub new_price: Option<f32>,
+    pub attempts: i32,
+    pub sent_channels: Vec<String>,
+    /// The store the new price is in
+    pub store_id: Option<i32>,
+    pub user_id: i32,
+    pub product_id: i32,
+}
+
+/// The result of a delivery attempt
+#[derive(AsChangeset)]
+#[diesel(table_name = delivered_notifications)]
+#[diesel(treat_none_as_null = true)]
+pub struct DeliveryOutcome {
+    pub delivered: bool,
+    pub attempts: i32,
+    /// None once the notification won't be retried
+    pub next_attempt_date: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub sent_channels: Vec<String>,
+}
+
+/// A price drop that wasn't delivered on its own and waits for the user's digest
+#[derive(Queryable)]
+pub struct DigestEntry {
+    pub id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+    pub old_price: Option<f32>,
+    pub new_price: Option<f32>,
+    pub created_date: NaiveDateTime,
+    /// The channels a digest with it already went out on
+    pub sent_channels: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct InboxFilter {
+    /// Only the notifications the user hasn't read
+    #[serde(default)]
+    pub unread: bool,
+    /// From 1 to 100000
+    pub page: Option<i64>,
+    /// From 1 to 100, 20 by default
+    pub per_page: Option<i64>,
+}
+
+/// A price drop in the user's in-app inbox
+#[derive(Serialize, ToSchema)]
+pub struct InboxNotificationDTO {
+    pub id: i32,
+    pub product: Product,
+    /// The store the new price is in
+    pub store_id: Option<i32>,
+    pub store_name: Option<String>,
+    pub old_price: Option<f32>,
+    pub new_price: Option<f32>,
+    /// The new price minus the old one, negative for a drop
+    pub price_change: Option<f32>,
+    /// The price change relative to the old price, in percent
+    pub price_change_percent: Option<f32>,
+    pub created_date: NaiveDateTime,
+    /// Null until the notification is read
+    pub read_date: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct InboxDTO {
+    pub notifications: Vec<InboxNotificationDTO>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The notifications matching the filter, on all pages
+    pub total: i64,
+    /// The unread notifications, whatever the filter
+    pub unread: i64,
+}
+
+type InboxRow = (
+    i32,
+    Product,
+    Option<i32>,
+    Option<String>,
+    Option<f32>,
+    Option<f32>,
+    NaiveDateTime,
+    Option<NaiveDateTime>,
+);
+
+/// The minimum price of a subscribed product went down since the last check, or met
+/// the target the first time it was seen
+pub struct PriceDrop {
+    pub notification_id: i32,
+    pub user_id: i32,
+    pub product_id: i32,
+    /// None if the first price seen already met the target
+    pub old_price: Option<f32>,
+    pub new_price: f32,
+}
+
+impl PriceDrop {
+    /// Compares the current minimum price of each subscribed product with the
+    /// subscription's baseline, records a notification for every drop, or for a first
+    /// price that already meets the target, and moves the baseline to the current price.
+    /// Returns nothing if another instance is running the check.
+    pub fn detect(conn: &mut PgConnection) -> QueryResult<Vec<PriceDrop>> {
+        conn.transaction(|conn| {
+            if !diesel::select(pg_try_advisory_xact_lock(PRICE_CHECK_LOCK_KEY))
+                .get_result::<bool>(conn)?
+            {
+                return Ok(Vec::new());
+            }
+
+            let subscriptions = user_subscribed_products
+                .select(UserSubscribedProduct::as_select())
+                .filter(subscribed.eq(true))
+                .load::<UserSubscribedProduct>(conn)?;
+            let mut product_ids = subscriptions
+                .iter()
+                .map(|subscription| subscription.product_id)
+                .collect::<Vec<i32>>();
+            product_ids.sort_unstable();
+            product_ids.dedup();
+            let latest_prices = Product::latest_prices(conn, &product_ids)?;
+
+            let mut price_drops = Vec::new();
+            for subscription in subscriptions {
+                // Not sold in the subscription's stores at the moment, keep the baseline
+                let Some(min_price) = min_price_for(&subscription, &latest_prices) else {
+                    continue;
+                };
+                let previous_price = match subscription.previous_minimal_price {
+                    Some(previous_price) if previous_price == min_price.price => continue,
+                    // Until the drop meets the conditions, it's measured from the old
+                    // baseline, so several small drops can add up
+                    Some(previous_price)
+                        if min_price.price < previous_price
+                            && !meets_conditions(
+                                &subscription,
+                                previous_price,
+                                min_price.price,
+                            ) =>
+                    {
+                        continue
+                    }
+                    previous_price => previous_price,
+                };
+
+                // Rises just move the baseline, and so does the first price seen, unless
+                // it already meets the target
+                let notify = match previous_price {
+                    Some(previous_price) => min_price.price < previous_price,
+                    None => subscription
+                        .target_price
+                        .map_or(false, |_target_price| min_price.price <= _target_price),
+                };
+                if notify {
+                    let notification_id = insert_into(delivered_notifications::table)
+                        .values((
+                            delivered_notifications::subscribe_id.eq(subscription.id),
+                            delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()),
+                            delivered_notifications::delivered.eq(false),
+                            delivered_notifications::created_date.eq(diesel::dsl::now),
+                            delivered_notifications::old_price.eq(previous_price),
+                            delivered_notifications::new_price.eq(min_price.price),
+                            delivered_notifications::store_id.eq(min_price.store_id),
+                            delivered_notifications::next_attempt_date.eq(Utc::now().naive_utc()),
+                        ))
+                        .returning(delivered_notifications::id)
+                        .get_result::<i32>(conn)?;
+
+                    price_drops.push(PriceDrop {
+                        notification_id,
+                        user_id: subscription.user_id,
+                        product_id: subscription.product_id,
+                        old_price: previous_price,
+                        new_price: min_price.price,
+                    });
+                }
+
+                update(user_subscribed_products::table)
+                    .filter(user_subscribed_products::id.eq(subscription.id))
+                    .set(previous_minimal_price.eq(min_price.price))
+                    .execute(conn)?;
+            }
+
+            Ok(price_drops)
+        })
+    }
+}
+
+// The lowest latest price in the stores the subscription is restricted to
+fn min_price_for<'a>(
+    subscription: &UserSubscribedProduct,
+    latest_prices: &'a [LatestPrice],
+) -> Option<&'a LatestPrice> {
+    let unrestricted =
+        subscription.store_ids.is_empty() && subscription.retail_chain_ids.is_empty();
+
+    latest_prices
+        .iter()
+        .filter(|latest_price| latest_price.product_id == subscription.product_id)
+        .filter(|latest_price| {
+            unrestricted
+                || subscription.store_ids.contains(&latest_price.store_id)
+                || subscription
+                    .retail_chain_ids
+                    .contains(&latest_price.retail_chain_id)
+        })
+        .min_by(|a, b| a.price.partial_cmp(&b.price).unwrap_or(Ordering::Equal))
+}
+
+fn meets_conditions(
+    subscription: &UserSubscribedProduct,
+    previous_price: f32,
+    new_price: f32,
+) -> bool {
+    let below_target = subscription
+        .target_price
+        .map_or(true, |_target_price| new_price <= _target_price);
+    let dropped_enough = subscription
+        .min_drop_percent
+        .map_or(true, |_min_drop_percent| {
+            previous_price > 0.0
+                && (previous_price - new_price) / previous_price * 100.0 >= _min_drop_percent
+        });
+
+    below_target && dropped_enough
+}
+
+impl PendingNotification {
+    /// Claims price drop notifications due for delivery. They aren't due again until the
+    /// lease runs out, so other instances skip them while they are being delivered.
+    pub fn claim_due(
+        conn: &mut PgConnection,
+        limit: i64,
+        lease_secs: i64,
+    ) -> QueryResult<Vec<PendingNotification>> {
+        conn.transaction(|conn| {
+            let _now = Utc::now().naive_utc();
+            let ids = delivered_notifications::table
+                .select(delivered_notifications::id)
+                .filter(delivered_notifications::delivered.eq(false))
+                .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+                .filter(delivered_notifications::next_attempt_date.le(_now))
+                .order_by(delivered_notifications::next_attempt_date)
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load::<i32>(conn)?;
+
+            update(delivered_notifications::table)
+                .filter(delivered_notifications::id.eq_any(&ids))
+                .set(
+                    delivered_notifications::next_attempt_date
+                        .eq(_now + Duration::seconds(lease_secs)),
+                )
+                .execute(conn)?;
+
+            delivered_notifications::table
+                .inner_join(user_subscribed_products::table)
+                .select((
+                    delivered_notifications::id,
+                    delivered_notifications::old_price,
+                    delivered_notifications::new_price,
+                    delivered_notifications::attempts,
+                    delivered_notifications::sent_channels,
+                    delivered_notifications::store_id,
+                    user_subscribed_products::user_id,
+                    user_subscribed_products::product_id,
+                ))
+                .filter(delivered_notifications::id.eq_any(&ids))
+                .order_by(delivered_notifications::id)
+                .load::<PendingNotification>(conn)
+        })
+    }
+
+    pub fn record(
+        conn: &mut PgConnection,
+        notification_id: i32,
+        outcome: &DeliveryOutcome,
+    ) -> QueryResult<usize> {
+        update(delivered_notifications::table)
+            .filter(delivered_notifications::id.eq(notification_id))
+            .set(outcome)
+            .execute(conn)
+    }
+}
+
+impl DigestEntry {
+    /// Users with price drops that are neither delivered nor going to be retried
+    pub fn user_ids(conn: &mut PgConnection) -> QueryResult<Vec<i32>> {
+        delivered_notifications::table
+            .inner_join(user_subscribed_products::table)
+            .select(user_subscribed_products::user_id)
+            .filter(delivered_notifications::delivered.eq(false))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+            .filter(delivered_notifications::next_attempt_date.is_null())
+            .distinct()
+            .load::<i32>(conn)
+    }
+
+    /// The user's price drops for the digest, oldest first
+    pub fn for_user(conn: &mut PgConnection, _user_id: i32) -> QueryResult<Vec<DigestEntry>> {
+        delivered_notifications::table
+            .inner_join(user_subscribed_products::table.inner_join(products::table))
+            .select((
+                delivered_notifications::id,
+                products::id,
+                products::name,
+                delivered_notifications::old_price,
+                delivered_notifications::new_price,
+                delivered_notifications::created_date,
+                delivered_notifications::sent_channels,
+            ))
+            .filter(user_subscribed_products::user_id.eq(_user_id))
+            .filter(delivered_notifications::delivered.eq(false))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+            .filter(delivered_notifications::next_attempt_date.is_null())
+            .order_by((
+                delivered_notifications::created_date,
+                delivered_notifications::id,
+            ))
+            .load::<DigestEntry>(conn)
+    }
+
+    /// Records that a digest with the entries went out on the channel
+    pub fn mark_sent(conn: &mut PgConnection, ids: &[i32], channel: &str) -> QueryResult<usize> {
+        update(delivered_notifications::table)
+            .filter(delivered_notifications::id.eq_any(ids))
+            .set(delivered_notifications::sent_channels.eq(array_append(
+                delivered_notifications::sent_channels,
+                channel,
+            )))
+            .execute(conn)
+    }
+
+    pub fn mark_delivered(conn: &mut PgConnection, ids: &[i32]) -> QueryResult<usize> {
+        update(delivered_notifications::table)
+            .filter(delivered_notifications::id.eq_any(ids))
+            .set(delivered_notifications::delivered.eq(true))
+            .execute(conn)
+    }
+}
+
+impl Validate for InboxFilter {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if self
+            .page
+            .map_or(false, |page| !(1..=INBOX_PAGE_MAX).contains(&page))
+        {
+            errors.push(FieldError::new(
+                "page",
+                format!("Page must be between 1 and {}", INBOX_PAGE_MAX),
+            ));
+        }
+        if self.per_page.map_or(false, |per_page| {
+            !(1..=INBOX_PAGE_SIZE_MAX).contains(&per_page)
+        }) {
+            errors.push(FieldError::new(
+                "per_page",
+                format!("Page size must be between 1 and {}", INBOX_PAGE_SIZE_MAX),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl InboxNotificationDTO {
+    /// The user's price drop notifications, newest first; expects a validated filter
+    pub fn get_inbox(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        filter: &InboxFilter,
+    ) -> QueryResult<InboxDTO> {
+        let page = filter.page.unwrap_or(1);
+        let per_page = filter.per_page.unwrap_or(INBOX_PAGE_SIZE_DEFAULT);
+
+        let mut query = delivered_notifications::table
+            .inner_join(user_subscribed_products::table.inner_join(products::table))
+            .left_join(stores::table)
+            .select((
+                delivered_notifications::id,
+                products::all_columns,
+                delivered_notifications::store_id,
+                stores::name.nullable(),
+                delivered_notifications::old_price,
+                delivered_notifications::new_price,
+                delivered_notifications::created_date,
+                delivered_notifications::read_date,
+            ))
+            .filter(user_subscribed_products::user_id.eq(_user_id))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+            .into_boxed();
+        if filter.unread {
+            query = query.filter(delivered_notifications::read_date.is_null());
+        }
+        let notifications = query
+            .order_by((
+                delivered_notifications::created_date.desc(),
+                delivered_notifications::id.desc(),
+            ))
+            .limit(per_page)
+            .offset((page - 1) * per_page)
+            .load::<InboxRow>(conn)?
+            .into_iter()
+            .map(InboxNotificationDTO::from)
+            .collect();
+
+        let unread = Self::count(conn, _user_id, true)?;
+        let total = if filter.unread {
+            unread
+        } else {
+            Self::count(conn, _user_id, false)?
+        };
+
+        Ok(InboxDTO {
+            notifications,
+            page,
+            per_page,
+            total,
+            unread,
+        })
+    }
+
+    fn count(conn: &mut PgConnection, _user_id: i32, only_unread: bool) -> QueryResult<i64> {
+        let mut query = delivered_notifications::table
+            .inner_join(user_subscribed_products::table)
+            .filter(user_subscribed_products::user_id.eq(_user_id))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+            .into_boxed();
+        if only_unread {
+            query = query.filter(delivered_notifications::read_date.is_null());
+        }
+
+        query.count().get_result(conn)
+    }
+
+    /// Marks the notification as read, unless it already is. False if the user has no
+    /// such notification.
+    pub fn mark_read(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        notification_id: i32,
+    ) -> QueryResult<bool> {
+        let user_subscriptions = user_subscribed_products::table
+            .select(user_subscribed_products::id)
+            .filter(user_subscribed_products::user_id.eq(_user_id));
+        let notification = delivered_notifications::table
+            .filter(delivered_notifications::id.eq(notification_id))
+            .filter(delivered_notifications::subscribe_id.eq_any(user_subscriptions))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()));
+
+        if !select(exists(notification)).get_result::<bool>(conn)? {
+            return Ok(false);
+        }
+
+        update(notification.filter(delivered_notifications::read_date.is_null()))
+            .set(delivered_notifications::read_date.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+
+        Ok(true)
+    }
+
+    /// Marks all the user's notifications as read, returning how many were unread
+    pub fn mark_all_read(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        let user_subscriptions = user_subscribed_products::table
+            .select(user_subscribed_products::id)
+            .filter(user_subscribed_products::user_id.eq(_user_id));
+
+        update(delivered_notifications::table)
+            .filter(delivered_notifications::subscribe_id.eq_any(user_subscriptions))
+            .filter(delivered_notifications::type_.eq(NotificationType::PriceDrop.as_str()))
+            .filter(delivered_notifications::read_date.is_null())
+            .set(delivered_notifications::read_date.eq(Utc::now().naive_utc()))
+            .execute(conn)
+    }
+}
+
+impl From<InboxRow> for InboxNotificationDTO {
+    fn from(
+        (
+            notification_id,
+            product,
+            _store_id,
+            store_name,
+            old_price,
+            new_price,
+            _created_date,
+            read_date,
+        ): InboxRow,
+    ) -> Self {
+        let (price_change, price_change_percent) = match (old_price, new_price) {
+            (Some(old_price), Some(new_price)) => (
+                Some(new_price - old_price),
+                Some(old_price)
+                    .filter(|&old_price| old_price > 0.0)
+                    .map(|old_price| (new_price - old_price) / old_price * 100.0),
+            ),
+            _ => (None, None),
+        };
+
+        InboxNotificationDTO {
+            id: notification_id,
+            product,
+            store_id: _store_id,
+            store_name,
+            old_price,
+            new_price,
+            price_change,
+            price_change_percent,
+            created_date: _created_date,
+            read_date,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::models::user_tokens::UserToken;
+use crate::schema::oidc_auth_requests::{self, dsl::*};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::insert_into;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// A login started at an OpenID Connect provider and not yet completed. It is looked
+/// up by the hash of the `state` the provider sends back.
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = oidc_auth_requests)]
+pub struct OidcAuthRequest {
+    pub id: i32,
+    pub state_hash: String,
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expiration_date: NaiveDateTime,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = oidc_auth_requests)]
+pub struct OidcAuthRequestInsertable {
+    pub state_hash: String,
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expiration_date: NaiveDateTime,
+}
+
+/// The plaintext values of a new request, to be put in the authorization URL
+pub struct PendingOidcAuth {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+impl OidcAuthRequest {
+    pub fn create(
+        conn: &mut PgConnection,
+        _provider: &str,
+        expires_in_secs: i64,
+    ) -> QueryResult<PendingOidcAuth> {
+        let now = Utc::now().naive_utc();
+        let pending = PendingOidcAuth {
+            state: random_string(32),
+            nonce: random_string(32),
+            // PKCE allows 43 to 128 characters
+            code_verifier: random_string(64),
+        };
+
+        // Abandoned logins are never consumed
+        diesel::delete(oidc_auth_requests.filter(expiration_date.lt(now))).execute(conn)?;
+
+        insert_into(oidc_auth_requests)
+            .values(OidcAuthRequestInsertable {
+                state_hash: UserToken::hash_token(&pending.state),
+                provider: _provider.to_string(),
+                code_verifier: pending.code_verifier.clone(),
+                nonce: pending.nonce.clone(),
+                expiration_date: now + Duration::seconds(expires_in_secs),
+            })
+            .execute(conn)?;
+
+        Ok(pending)
+    }
+
+    /// Removes the request and returns it, or `None` if the state is unknown, expired
+    /// or was already used.
+    pub fn consume(conn: &mut PgConnection, state: &str) -> QueryResult<Option<OidcAuthRequest>> {
+        diesel::delete(
+            oidc_auth_requests
+                .filter(state_hash.eq(UserToken::hash_token(state)))
+                .filter(expiration_date.gt(Utc::now().naive_utc())),
+        )
+        .get_result::<OidcAuthRequest>(conn)
+        .optional()
+    }
+}
@@ -1,9 +1,14 @@
-use crate::models::store::Store;
-use crate::models::user::{UserSubscribedProduct, UserSubscribedProductDTO};
+use crate::models::store::{Store, StoreScope};
+use crate::models::user::{
+    SubscriptionConditionsDTO, UserSubscribedProduct, UserSubscribedProductDTO,
+};
+use crate::models::user_settings::PriceScope;
+use crate::models::validation::FieldError;
 use crate::schema::product_store_prices::{self, dsl::*};
 use crate::schema::product_stores::{self, dsl::*};
 use crate::schema::products::{self, dsl::*};
 use crate::schema::user_subscribed_products::{self, dsl::*};
+use crate::schema::{retail_chains, stores};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::{insert_into, update};
@@ -46,6 +51,19 @@ pub struct ProductStorePrice {
     pub product_store_id: i32,
 }
 
+#[derive(Queryable)]
+pub struct LatestPrice {
+    pub product_id: i32,
+    pub store_id: i32,
+    pub retail_chain_id: i32,
+    pub price: f32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewPriceDTO {
+    pub price: f32,
+}
+
 #[derive(Deserialize, IntoParams)]
 pub struct ProductFilter {
     query: Option<String>,
@@ -53,6 +71,66 @@ pub struct ProductFilter {
     min_price: Option<f32>,
     max_price: Option<f32>,
     limit: Option<i16>,
+    /// Defaults to the logged in user's home city, or country if they haven't picked one
+    pub scope: Option<PriceScope>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ProductQuery {
+    /// Defaults to the logged in user's home city, or country if they haven't picked one
+    pub scope: Option<PriceScope>,
+}
+
+// Streams are for a watch list, not the whole catalog
+pub const PRICE_STREAM_PRODUCTS_MAX: usize = 100;
+
+/// A price added to a store, as streamed to the clients watching the product
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct PriceUpdateDTO {
+    pub id: i32,
+    pub product_id: i32,
+    pub store_id: i32,
+    pub store_name: String,
+    pub product_store_id: i32,
+    pub price: f32,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PriceStreamQuery {
+    /// Comma separated, at most 100. Defaults to the logged in user's subscribed products.
+    #[param(example = "1,2,3")]
+    pub product_ids: Option<String>,
+}
+
+impl PriceStreamQuery {
+    pub fn parse_product_ids(&self) -> Result<Option<HashSet<i32>>, Vec<FieldError>> {
+        let Some(ids) = &self.product_ids else {
+            return Ok(None);
+        };
+
+        let ids = ids
+            .split(',')
+            .map(|_id| _id.trim().parse::<i32>())
+            .collect::<Result<HashSet<i32>, _>>()
+            .map_err(|_| {
+                vec![FieldError::new(
+                    "product_ids",
+                    "Product ids must be comma separated numbers".to_string(),
+                )]
+            })?;
+        if ids.len() > PRICE_STREAM_PRODUCTS_MAX {
+            return Err(vec![FieldError::new(
+                "product_ids",
+                format!(
+                    "At most {} products can be streamed at once",
+                    PRICE_STREAM_PRODUCTS_MAX
+                ),
+            )]);
+        }
+
+        Ok(Some(ids))
+    }
 }
 
 #[derive(Serialize, ToSchema, PartialEq)]
@@ -66,8 +144,8 @@ pub struct ProductStorePriceDTO {
 // TODO: Replace Product struct
 #[derive(Serialize, ToSchema)]
 pub struct ProductDTO {
-    product: Product,
-    prices: Vec<ProductStorePriceDTO>,
+    pub product: Product,
+    pub prices: Vec<ProductStorePriceDTO>,
     min_price: Option<f32>,
     max_price: Option<f32>,
 }
@@ -138,9 +216,12 @@ impl Product {
         }
     }
 
+    /// Products with prices matching the filter. With a scope, only stores in it are
+    /// considered.
     pub fn get_products_by_filter(
         conn: &mut PgConnection,
         filter: ProductFilter,
+        scope: Option<StoreScope>,
     ) -> QueryResult<Vec<ProductDTO>> {
         let mut products_query = products::table
             .select(Product::as_select())
@@ -178,11 +259,22 @@ impl Product {
         products_query =
             products_query.filter(product_stores::id.eq_any(product_store_ids_with_prices));
 
+        if let Some(ref scope) = scope {
+            products_query = products_query.filter(store_id.eq_any(Store::ids_in_scope(scope)));
+        }
+
         let filtered_products = products_query.load::<Product>(conn)?;
 
-        let filtered_product_stores = ProductStore::belonging_to(&filtered_products)
+        let mut product_stores_query = ProductStore::belonging_to(&filtered_products)
             .select(ProductStore::as_select())
-            .load::<ProductStore>(conn)?;
+            .into_boxed();
+
+        if let Some(ref scope) = scope {
+            product_stores_query =
+                product_stores_query.filter(store_id.eq_any(Store::ids_in_scope(scope)));
+        }
+
+        let filtered_product_stores = product_stores_query.load::<ProductStore>(conn)?;
 
         let prices_query = ProductStorePrice::belonging_to(&filtered_product_stores)
             .select(ProductStorePrice::as_select())
@@ -228,10 +320,22 @@ impl Product {
             }))
     }
 
-    pub fn get_product(conn: &mut PgConnection, _product_id: i32) -> QueryResult<ProductDTO> {
-        let _product_stores = product_stores
+    /// The product with its latest price in each store, only in the scope if there is one
+    pub fn get_product(
+        conn: &mut PgConnection,
+        _product_id: i32,
+        scope: Option<StoreScope>,
+    ) -> QueryResult<ProductDTO> {
+        let mut product_stores_query = product_stores
             .filter(product_stores::product_id.eq(_product_id))
-            .load::<ProductStore>(conn)?;
+            .into_boxed();
+
+        if let Some(ref scope) = scope {
+            product_stores_query =
+                product_stores_query.filter(store_id.eq_any(Store::ids_in_scope(scope)));
+        }
+
+        let _product_stores = product_stores_query.load::<ProductStore>(conn)?;
 
         let prices = ProductStorePrice::belonging_to(&_product_stores)
             .select(ProductStorePrice::as_select())
@@ -246,7 +350,7 @@ impl Product {
             })
             .collect::<Vec<ProductDTO>>();
 
-        Ok(product_dto
+        product_dto
             .into_iter()
             .fold(Vec::<ProductDTO>::new(), |mut acc, product_dto| {
                 if let Some(existing_product_dto) = acc
@@ -274,7 +378,18 @@ impl Product {
                 acc
             })
             .pop()
-            .unwrap())
+            .map_or_else(
+                // Not sold in any store in the scope
+                || {
+                    Ok(ProductDTO {
+                        product: Self::find_product_by_id(conn, _product_id)?,
+                        prices: Vec::new(),
+                        min_price: None,
+                        max_price: None,
+                    })
+                },
+                Ok,
+            )
     }
 
     pub fn get_product_by_product_store_id(
@@ -315,40 +430,104 @@ impl Product {
             .filter(user_subscribed_products::product_id.eq(_product_id))
             .first::<UserSubscribedProduct>(conn)
         {
-            UserSubscribedProductDTO {
-                product_id: subscription.product_id,
-                subscribed: subscription.subscribed,
-            }
+            UserSubscribedProductDTO::from(subscription)
         } else {
             UserSubscribedProductDTO {
                 product_id: _product_id,
                 subscribed: false,
+                target_price: None,
+                min_drop_percent: None,
+                store_ids: Vec::new(),
+                retail_chain_ids: Vec::new(),
             }
         }
     }
 
+    /// Subscribes with the conditions, replacing any previous ones. The baseline price is
+    /// reset, since it may have come from other stores.
     pub fn subscribe_to_product(
         conn: &mut PgConnection,
         _user_id: i32,
         _product_id: i32,
-    ) -> QueryResult<usize> {
-        if let Ok(_) = user_subscribed_products
+        conditions: SubscriptionConditionsDTO,
+    ) -> QueryResult<Result<usize, Vec<FieldError>>> {
+        if let Err(errors) = Self::validate_subscription_stores(conn, &conditions)? {
+            return Ok(Err(errors));
+        }
+
+        let values = (
+            subscribed.eq(true),
+            previous_minimal_price.eq(None::<f32>),
+            target_price.eq(conditions.target_price),
+            min_drop_percent.eq(conditions.min_drop_percent),
+            store_ids.eq(&conditions.store_ids),
+            retail_chain_ids.eq(&conditions.retail_chain_ids),
+        );
+
+        if user_subscribed_products
             .filter(user_id.eq(_user_id))
             .filter(user_subscribed_products::product_id.eq(_product_id))
             .first::<UserSubscribedProduct>(conn)
+            .is_ok()
         {
             update(user_subscribed_products::table)
                 .filter(user_id.eq(_user_id))
                 .filter(user_subscribed_products::product_id.eq(_product_id))
-                .set(subscribed.eq(true))
+                .set(values)
                 .execute(conn)
+                .map(Ok)
         } else {
             insert_into(user_subscribed_products::table)
                 .values((
                     user_id.eq(_user_id),
                     user_subscribed_products::product_id.eq(_product_id),
+                    values,
                 ))
                 .execute(conn)
+                .map(Ok)
+        }
+    }
+
+    fn validate_subscription_stores(
+        conn: &mut PgConnection,
+        conditions: &SubscriptionConditionsDTO,
+    ) -> QueryResult<Result<(), Vec<FieldError>>> {
+        let mut errors = Vec::new();
+
+        let existing_store_ids = stores::table
+            .select(stores::id)
+            .filter(stores::id.eq_any(&conditions.store_ids))
+            .load::<i32>(conn)?;
+        if let Some(unknown_id) = conditions
+            .store_ids
+            .iter()
+            .find(|_store_id| !existing_store_ids.contains(_store_id))
+        {
+            errors.push(FieldError::new(
+                "store_ids",
+                format!("Store {} does not exist", unknown_id),
+            ));
+        }
+
+        let existing_retail_chain_ids = retail_chains::table
+            .select(retail_chains::id)
+            .filter(retail_chains::id.eq_any(&conditions.retail_chain_ids))
+            .load::<i32>(conn)?;
+        if let Some(unknown_id) = conditions
+            .retail_chain_ids
+            .iter()
+            .find(|retail_chain_id| !existing_retail_chain_ids.contains(retail_chain_id))
+        {
+            errors.push(FieldError::new(
+                "retail_chain_ids",
+                format!("Retail chain {} does not exist", unknown_id),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(errors))
         }
     }
 
@@ -358,11 +537,12 @@ impl Product {
         _product_id: i32,
     ) -> QueryResult<usize> {
         // check if user is already unsubscribed
-        if let Ok(_) = user_subscribed_products
+        if user_subscribed_products
             .filter(user_id.eq(_user_id))
             .filter(user_subscribed_products::product_id.eq(_product_id))
             .filter(subscribed.eq(false))
             .first::<UserSubscribedProduct>(conn)
+            .is_ok()
         {
             return Ok(0);
         }
@@ -374,6 +554,29 @@ impl Product {
             .execute(conn)
     }
 
+    /// The latest price in each store selling one of the products
+    pub fn latest_prices(
+        conn: &mut PgConnection,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<LatestPrice>> {
+        product_store_prices
+            .inner_join(product_stores.inner_join(stores::table))
+            .filter(product_stores::product_id.eq_any(product_ids))
+            .distinct_on(product_store_prices::product_store_id)
+            .order_by((
+                product_store_prices::product_store_id,
+                product_store_prices::created_date.desc(),
+                product_store_prices::id.desc(),
+            ))
+            .select((
+                product_stores::product_id,
+                product_stores::store_id,
+                stores::retail_chain_id,
+                price,
+            ))
+            .load::<LatestPrice>(conn)
+    }
+
     pub fn find_product_by_id(conn: &mut PgConnection, _id: i32) -> QueryResult<Product> {
         products
             .select(Product::as_select())
@@ -381,6 +584,37 @@ impl Product {
             .first(conn)
     }
 
+    /// Records a new price for the product in the store, e.g. from a scraper
+    pub fn add_price(
+        conn: &mut PgConnection,
+        _product_store_id: i32,
+        new_price: NewPriceDTO,
+    ) -> Result<usize, String> {
+        if !new_price.price.is_finite() || new_price.price < 0.0 {
+            return Err("Price must be a non-negative number".to_string());
+        }
+
+        conn.transaction(|conn| {
+            let product_store = Self::find_product_store_by_id(conn, _product_store_id)?;
+
+            update(&product_store)
+                .set(product_stores::updated_date.eq(diesel::dsl::now))
+                .execute(conn)?;
+
+            insert_into(product_store_prices)
+                .values((
+                    product_store_prices::product_store_id.eq(product_store.id),
+                    price.eq(new_price.price),
+                    product_store_prices::created_date.eq(diesel::dsl::now),
+                ))
+                .execute(conn)
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => "Product store not found".to_string(),
+            e => e.to_string(),
+        })
+    }
+
     pub fn find_product_store_by_id(
         conn: &mut PgConnection,
         _id: i32,
@@ -1,9 +1,19 @@
+use crate::models::api_key::{ApiKeyDTO, CreatedApiKeyDTO};
 use crate::models::category::Category;
+use crate::models::notification::InboxDTO;
 use crate::models::product::{ProductDTO, ProductStoreDTO};
+use crate::models::role::Role;
+use crate::models::two_factor::{RecoveryCodesDTO, TwoFactorEnrollmentDTO};
 use crate::models::user::{
-    HistoryWithProductDTO, PasswordRequirements, UserShoppingCartDTO, UserSubscribedProductDTO,
+    HistoryWithProductDTO, LoginResultDTO, PasswordRequirements, UserProfileDTO,
+    UserShoppingCartDTO, UserSubscribedProductDTO,
 };
-use crate::models::user_tokens::UserTokensDTO;
+use crate::models::user_access::UserAccessDTO;
+use crate::models::user_identity::OidcAuthorizationDTO;
+use crate::models::user_notification_settings::UserNotificationSettingsDTO;
+use crate::models::user_settings::UserSettingsDTO;
+use crate::models::user_tokens::{SessionDTO, UserTokensDTO};
+use crate::models::validation::FieldError;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -19,7 +29,22 @@ use utoipa::ToSchema;
     ResponseVecShoppingCart = ResponseBody<Vec<UserShoppingCartDTO>>,
     ResponseVecCategory = ResponseBody<Vec<Category>>,
     ResponseVecHistory = ResponseBody<Vec<HistoryWithProductDTO>>,
+    ResponseVecRole = ResponseBody<Vec<Role>>,
     ResponseSubscriptions = ResponseBody<Vec<UserSubscribedProductDTO>>,
+    ResponseVecUserAccess = ResponseBody<Vec<UserAccessDTO>>,
+    ResponseUserProfile = ResponseBody<UserProfileDTO>,
+    ResponseLoginResult = ResponseBody<LoginResultDTO>,
+    ResponseTwoFactorEnrollment = ResponseBody<TwoFactorEnrollmentDTO>,
+    ResponseRecoveryCodes = ResponseBody<RecoveryCodesDTO>,
+    ResponseVecApiKey = ResponseBody<Vec<ApiKeyDTO>>,
+    ResponseCreatedApiKey = ResponseBody<CreatedApiKeyDTO>,
+    ResponseVecSession = ResponseBody<Vec<SessionDTO>>,
+    ResponseValidationErrors = ResponseBody<Vec<FieldError>>,
+    ResponseOidcAuthorization = ResponseBody<OidcAuthorizationDTO>,
+    ResponseVecString = ResponseBody<Vec<String>>,
+    ResponseUserSettings = ResponseBody<UserSettingsDTO>,
+    ResponseUserNotificationSettings = ResponseBody<UserNotificationSettingsDTO>,
+    ResponseInbox = ResponseBody<InboxDTO>,
     ResponseCartTotalPrice = ResponseBody<f32>
 )]
 pub struct ResponseBody<T> {
@@ -0,0 +1,73 @@
+use crate::models::user::User;
+use crate::schema::roles::{self, dsl::*};
+use crate::schema::user_roles;
+use diesel::insert_into;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, ToSchema)]
+#[diesel(table_name = roles)]
+pub struct Role {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl Role {
+    pub fn get_roles(conn: &mut PgConnection) -> QueryResult<Vec<Role>> {
+        roles.order_by(roles::id).get_results::<Role>(conn)
+    }
+
+    pub fn find_role_by_name(conn: &mut PgConnection, _name: &str) -> QueryResult<Role> {
+        roles.filter(name.eq(_name)).get_result::<Role>(conn)
+    }
+
+    pub fn get_user_role_names(conn: &mut PgConnection, _user_id: i32) -> QueryResult<Vec<String>> {
+        user_roles::table
+            .inner_join(roles)
+            .select(name)
+            .filter(user_roles::user_id.eq(_user_id))
+            .order_by(name)
+            .get_results::<String>(conn)
+    }
+
+    pub fn assign_role(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        role_name: &str,
+    ) -> QueryResult<usize> {
+        let role = Self::find_role_by_name(conn, role_name)?;
+
+        insert_into(user_roles::table)
+            .values((
+                user_roles::user_id.eq(_user_id),
+                user_roles::role_id.eq(role.id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)
+    }
+
+    /// Revoking also signs the user out everywhere, as roles are carried in access tokens
+    pub fn revoke_role(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        role_name: &str,
+    ) -> QueryResult<usize> {
+        let role = Self::find_role_by_name(conn, role_name)?;
+
+        conn.transaction(|conn| {
+            let revoked = diesel::delete(
+                user_roles::table
+                    .filter(user_roles::user_id.eq(_user_id))
+                    .filter(user_roles::role_id.eq(role.id)),
+            )
+            .execute(conn)?;
+            if revoked > 0 {
+                User::logout_all(conn, _user_id)?;
+            }
+
+            Ok(revoked)
+        })
+    }
+}
@@ -2,7 +2,9 @@ use crate::schema::product_stores::store_id;
 use crate::schema::product_stores::{self, dsl::*};
 use crate::schema::stores::{self, dsl::*};
 use chrono::NaiveDateTime;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::sql_types::Integer;
 use serde::Serialize;
 
 #[derive(Queryable, Identifiable, Selectable, Serialize)]
@@ -20,7 +22,27 @@ pub struct Store {
     pub created_date: NaiveDateTime,
 }
 
+/// The area to show prices from: a country, or a city in it
+pub struct StoreScope {
+    pub country_id: i32,
+    pub city_id: Option<i32>,
+}
+
 impl Store {
+    /// The ids of the stores in the scope, as a subquery
+    pub fn ids_in_scope(scope: &StoreScope) -> stores::BoxedQuery<'static, Pg, Integer> {
+        let mut query = stores
+            .select(stores::id)
+            .filter(country_id.eq(scope.country_id))
+            .into_boxed();
+
+        if let Some(_city_id) = scope.city_id {
+            query = query.filter(city_id.eq(_city_id));
+        }
+
+        query
+    }
+
     pub fn get_store_name_by_id(conn: &mut PgConnection, _store_id: i32) -> QueryResult<String> {
         stores
             .select(name)
@@ -0,0 +1,311 @@
+use crate::config::app::Config;
+use crate::models::user::User;
+use crate::models::user_tokens::UserToken;
+use crate::schema::user_recovery_codes::{self, dsl::*};
+use crate::schema::users;
+use chrono::{Duration, NaiveDateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use diesel::insert_into;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use utoipa::ToSchema;
+
+// RFC 6238 defaults, which is what authenticator apps expect
+const TOTP_STEP_SECS: i64 = 30;
+const TOTP_DIGITS: usize = 6;
+const RECOVERY_CODES_COUNT: usize = 10;
+const CHALLENGE_PURPOSE: &str = "two_factor";
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = user_recovery_codes)]
+pub struct UserRecoveryCode {
+    pub id: i32,
+    pub user_id: i32,
+    pub code_hash: String,
+    pub used_date: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_recovery_codes)]
+pub struct UserRecoveryCodeInsertable {
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TwoFactorEnrollmentDTO {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFactorCodeDTO {
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DisableTwoFactorDTO {
+    pub password: String,
+    /// A code from the authenticator app or a recovery code
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodesDTO {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TwoFactorChallengeDTO {
+    pub challenge_token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFactorLoginDTO {
+    pub challenge_token: String,
+    /// A code from the authenticator app or a recovery code
+    pub code: String,
+}
+
+/// Claims of the token handed out after the password check when 2FA is on.
+/// It can't be used as an access token, since it lacks the access token claims.
+#[derive(Serialize, Deserialize)]
+struct TwoFactorChallengeClaims {
+    sub: String,
+    iat: usize,
+    exp: usize,
+    purpose: String,
+}
+
+pub struct TwoFactor;
+
+impl TwoFactor {
+    /// Generates a new secret for the user. It only takes effect once a code generated
+    /// from it is confirmed.
+    pub fn enroll(
+        conn: &mut PgConnection,
+        user: &User,
+        config: &Config,
+    ) -> Result<TwoFactorEnrollmentDTO, String> {
+        if user.totp_enabled {
+            return Err("Two-factor authentication is already enabled".to_string());
+        }
+
+        let secret = BASE32_NOPAD.encode(&rand::thread_rng().gen::<[u8; 20]>());
+
+        diesel::update(users::table.filter(users::id.eq(user.id)))
+            .set(users::totp_secret.eq(&secret))
+            .execute(conn)
+            .map_err(|e| e.to_string())?;
+
+        let label = format!("{}:{}", config.two_factor_issuer, user.login);
+        let otpauth_uri = format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            percent_encode(&label),
+            secret,
+            percent_encode(&config.two_factor_issuer),
+            TOTP_DIGITS,
+            TOTP_STEP_SECS
+        );
+
+        Ok(TwoFactorEnrollmentDTO {
+            secret,
+            otpauth_uri,
+        })
+    }
+
+    /// Enables 2FA once the user proves their app generates valid codes,
+    /// and returns a fresh set of recovery codes.
+    pub fn confirm(
+        conn: &mut PgConnection,
+        user: &User,
+        code: &str,
+    ) -> Result<RecoveryCodesDTO, String> {
+        if user.totp_enabled {
+            return Err("Two-factor authentication is already enabled".to_string());
+        }
+        if user.totp_secret.is_none() {
+            return Err("Two-factor authentication enrollment wasn't started".to_string());
+        }
+
+        conn.transaction(|conn| {
+            if !Self::verify_totp(conn, user, code)? {
+                return Ok(Err("Two-factor code is wrong!".to_string()));
+            }
+
+            diesel::update(users::table.filter(users::id.eq(user.id)))
+                .set(users::totp_enabled.eq(true))
+                .execute(conn)?;
+
+            Ok(Ok(RecoveryCodesDTO {
+                recovery_codes: Self::generate_recovery_codes(conn, user.id)?,
+            }))
+        })
+        .map_err(|e: diesel::result::Error| e.to_string())?
+    }
+
+    pub fn disable(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            diesel::delete(user_recovery_codes.filter(user_id.eq(_user_id))).execute(conn)?;
+
+            diesel::update(users::table.filter(users::id.eq(_user_id)))
+                .set((
+                    users::totp_secret.eq(None::<String>),
+                    users::totp_enabled.eq(false),
+                    users::totp_last_used_step.eq(None::<i64>),
+                ))
+                .execute(conn)
+        })
+    }
+
+    /// Checks a code from the authenticator app or, failing that, a recovery code.
+    /// Either can only be used once.
+    pub fn verify(conn: &mut PgConnection, user: &User, code: &str) -> QueryResult<bool> {
+        if code.trim().len() == TOTP_DIGITS {
+            return Self::verify_totp(conn, user, code);
+        }
+
+        let normalized = code
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+
+        diesel::update(
+            user_recovery_codes
+                .filter(user_id.eq(user.id))
+                .filter(code_hash.eq(UserToken::hash_token(&normalized)))
+                .filter(used_date.is_null()),
+        )
+        .set(used_date.eq(Utc::now().naive_utc()))
+        .execute(conn)
+        .map(|count| count > 0)
+    }
+
+    pub fn issue_challenge(user: &User, config: &Config) -> String {
+        let now = Utc::now();
+        let claims = TwoFactorChallengeClaims {
+            sub: user.id.to_string(),
+            iat: now.timestamp() as usize,
+            exp: (now + Duration::seconds(config.two_factor_challenge_expires_in_secs)).timestamp()
+                as usize,
+            purpose: CHALLENGE_PURPOSE.to_string(),
+        };
+
+        config.jwt_keys.encode(&claims).unwrap()
+    }
+
+    /// Returns the id of the user the challenge was issued to, if it is valid
+    pub fn decode_challenge(token: &str, config: &Config) -> Option<i32> {
+        let claims = config
+            .jwt_keys
+            .decode::<TwoFactorChallengeClaims>(token)
+            .ok()?;
+        if claims.purpose != CHALLENGE_PURPOSE {
+            return None;
+        }
+
+        claims.sub.parse().ok()
+    }
+
+    // Accepts the codes of the previous, current and next step to allow for clock drift,
+    // but never a step at or before the last one used, so a code can't be replayed
+    fn verify_totp(conn: &mut PgConnection, user: &User, code: &str) -> QueryResult<bool> {
+        let secret = match user
+            .totp_secret
+            .as_ref()
+            .and_then(|secret| BASE32_NOPAD.decode(secret.as_bytes()).ok())
+        {
+            Some(secret) => secret,
+            None => return Ok(false),
+        };
+        let code = match code.trim().parse::<u32>() {
+            Ok(code) => code,
+            Err(_) => return Ok(false),
+        };
+
+        let current_step = Utc::now().timestamp() / TOTP_STEP_SECS;
+        let matching_step = (current_step - 1..=current_step + 1)
+            .filter(|step| user.totp_last_used_step.map_or(true, |last| *step > last))
+            .find(|step| totp(&secret, *step as u64) == code);
+
+        match matching_step {
+            Some(step) => diesel::update(
+                users::table.filter(users::id.eq(user.id)).filter(
+                    users::totp_last_used_step
+                        .is_null()
+                        .or(users::totp_last_used_step.lt(step)),
+                ),
+            )
+            .set(users::totp_last_used_step.eq(step))
+            .execute(conn)
+            .map(|count| count > 0),
+            None => Ok(false),
+        }
+    }
+
+    fn generate_recovery_codes(conn: &mut PgConnection, _user_id: i32) -> QueryResult<Vec<String>> {
+        diesel::delete(user_recovery_codes.filter(user_id.eq(_user_id))).execute(conn)?;
+
+        let codes = (0..RECOVERY_CODES_COUNT)
+            .map(|_| {
+                rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(10)
+                    .map(char::from)
+                    .collect::<String>()
+                    .to_lowercase()
+            })
+            .collect::<Vec<String>>();
+
+        insert_into(user_recovery_codes)
+            .values(
+                codes
+                    .iter()
+                    .map(|code| UserRecoveryCodeInsertable {
+                        user_id: _user_id,
+                        code_hash: UserToken::hash_token(code),
+                    })
+                    .collect::<Vec<UserRecoveryCodeInsertable>>(),
+            )
+            .execute(conn)?;
+
+        // Shown split in two halves for readability; dashes are ignored on input
+        Ok(codes
+            .into_iter()
+            .map(|code| format!("{}-{}", &code[..5], &code[5..]))
+            .collect())
+    }
+}
+
+fn totp(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    binary % 10_u32.pow(TOTP_DIGITS as u32)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -1,6 +1,20 @@
 use crate::config::app::Config;
+use crate::models::api_key::ApiKey;
 use crate::models::product::{Product, ProductStore};
-use crate::models::user_tokens::{UserRefreshTokenDTO, UserToken, UserTokensDTO};
+use crate::models::two_factor::{TwoFactor, TwoFactorChallengeDTO};
+use crate::models::user_access::{ClientInfo, UserAccess};
+use crate::models::user_data::UserDataExport;
+use crate::models::user_identity::{UserIdentity, UserIdentityInsertable};
+use crate::models::user_notification_settings::UserNotificationSettings;
+use crate::models::user_one_time_token::{TokenPurpose, UserOneTimeToken};
+use crate::models::user_tokens::{
+    RefreshTokenError, UserRefreshTokenDTO, UserToken, UserTokensDTO,
+};
+use crate::models::validation::{
+    email_error, login_error, normalize_email, FieldError, Validate, EMAIL_MAX_LENGTH,
+    LOGIN_MAX_LENGTH, LOGIN_MIN_LENGTH, NICKNAME_MAX_LENGTH,
+};
+use crate::oidc::IdTokenClaims;
 use crate::schema::user_product_history::user_id;
 use crate::schema::user_product_history::{self, dsl::*};
 use crate::schema::user_shopping_carts;
@@ -13,10 +27,16 @@ use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use chrono::NaiveDateTime;
 use diesel::insert_into;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+use diesel::sql_types::Text;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, ToSchema)]
+sql_function!(fn lower(x: Text) -> Text);
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
 pub struct PasswordRequirements {
     pub min_length: u32,
     pub max_length: u32,
@@ -24,46 +44,44 @@ pub struct PasswordRequirements {
     pub min_lowercase: u32,
     pub min_digits: u32,
     pub min_special: u32,
+    /// Whether common and breached passwords are rejected
+    pub blocklist_enabled: bool,
 }
 
 impl PasswordRequirements {
-    pub fn default() -> PasswordRequirements {
-        PasswordRequirements {
-            min_length: 8,
-            max_length: 32,
-            min_uppercase: 1,
-            min_lowercase: 1,
-            min_digits: 1,
-            min_special: 1,
-        }
-    }
-
+    /// Checks the length and character classes. Lengths are counted in characters and
+    /// classes follow the Unicode categories, so e.g. 'É' is an uppercase letter. Letters
+    /// without case, such as CJK characters, count towards the length only.
     pub fn validate(&self, _password: &str) -> Result<(), String> {
+        let mut length = 0;
         let mut uppercase = 0;
         let mut lowercase = 0;
         let mut digits = 0;
         let mut special = 0;
 
         for c in _password.chars() {
-            if c.is_ascii_uppercase() {
+            length += 1;
+            if c.is_uppercase() {
                 uppercase += 1;
-            } else if c.is_ascii_lowercase() {
+            } else if c.is_lowercase() {
                 lowercase += 1;
-            } else if c.is_ascii_digit() {
+            } else if c.is_numeric() {
                 digits += 1;
-            } else {
+            } else if c.is_control() {
+                return Err("Password must not contain control characters".to_string());
+            } else if !c.is_alphabetic() {
                 special += 1;
             }
         }
 
-        if _password.len() < self.min_length as usize {
+        if length < self.min_length {
             return Err(format!(
                 "Password must be at least {} characters long",
                 self.min_length
             ));
         }
 
-        if _password.len() > self.max_length as usize {
+        if length > self.max_length {
             return Err(format!(
                 "Password must be at most {} characters long",
                 self.max_length
@@ -111,6 +129,26 @@ pub struct User {
     pub password: String,
     pub created_date: NaiveDateTime,
     pub updated_date: NaiveDateTime,
+    pub token_version: i32,
+    pub email_verified: bool,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_used_step: Option<i64>,
+    pub disabled: bool,
+}
+
+impl From<User> for UserProfileDTO {
+    fn from(user: User) -> Self {
+        UserProfileDTO {
+            id: user.id,
+            nickname: user.nickname,
+            login: user.login,
+            email: user.email,
+            email_verified: user.email_verified,
+            two_factor_enabled: user.totp_enabled,
+            created_date: user.created_date,
+        }
+    }
 }
 
 #[derive(Queryable, Associations, Selectable, Insertable, Serialize)]
@@ -146,6 +184,10 @@ pub struct UserSubscribedProduct {
     pub previous_minimal_price: Option<f32>,
     pub subscribed: bool,
     pub created_date: NaiveDateTime,
+    pub target_price: Option<f32>,
+    pub min_drop_percent: Option<f32>,
+    pub store_ids: Vec<i32>,
+    pub retail_chain_ids: Vec<i32>,
 }
 
 #[derive(Queryable, Associations, Selectable, Insertable, Serialize)]
@@ -166,6 +208,42 @@ pub struct UserDTO {
     pub password: String,
 }
 
+impl UserDTO {
+    /// Trims the login and lower-cases the email, as it is stored
+    pub fn normalized(self) -> UserDTO {
+        UserDTO {
+            login: self.login.trim().to_string(),
+            email: normalize_email(&self.email),
+            ..self
+        }
+    }
+}
+
+impl Validate for UserDTO {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if let Some(message) = login_error(&self.login) {
+            errors.push(FieldError::new("login", message));
+        }
+        if let Some(message) = email_error(&self.email) {
+            errors.push(FieldError::new("email", message));
+        }
+        if self.password.is_empty() {
+            errors.push(FieldError::new(
+                "password",
+                "Password must not be empty".to_string(),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 #[derive(Insertable, Serialize, Deserialize, ToSchema)]
 #[diesel(table_name = user_shopping_carts)]
 pub struct UserShoppingCartDTO {
@@ -178,6 +256,70 @@ pub struct UserShoppingCartDTO {
 pub struct UserSubscribedProductDTO {
     pub product_id: i32,
     pub subscribed: bool,
+    pub target_price: Option<f32>,
+    pub min_drop_percent: Option<f32>,
+    pub store_ids: Vec<i32>,
+    pub retail_chain_ids: Vec<i32>,
+}
+
+impl From<UserSubscribedProduct> for UserSubscribedProductDTO {
+    fn from(subscription: UserSubscribedProduct) -> Self {
+        UserSubscribedProductDTO {
+            product_id: subscription.product_id,
+            subscribed: subscription.subscribed,
+            target_price: subscription.target_price,
+            min_drop_percent: subscription.min_drop_percent,
+            store_ids: subscription.store_ids,
+            retail_chain_ids: subscription.retail_chain_ids,
+        }
+    }
+}
+
+/// When to be notified about a subscribed product. Without any conditions, every drop
+/// of its minimum price is.
+#[derive(Default, Deserialize, ToSchema)]
+pub struct SubscriptionConditionsDTO {
+    /// Only notify once the minimum price is at or below it, which the first price seen
+    /// may already be
+    pub target_price: Option<f32>,
+    /// Only notify when the price dropped by at least this many percent since it was last
+    /// notified, rose or was first seen
+    pub min_drop_percent: Option<f32>,
+    /// Only consider prices in these stores and the stores of `retail_chain_ids`; all
+    /// stores if both are empty
+    #[serde(default)]
+    pub store_ids: Vec<i32>,
+    #[serde(default)]
+    pub retail_chain_ids: Vec<i32>,
+}
+
+impl Validate for SubscriptionConditionsDTO {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if let Some(target_price) = self.target_price {
+            if !target_price.is_finite() || target_price <= 0.0 {
+                errors.push(FieldError::new(
+                    "target_price",
+                    "Target price must be a positive number".to_string(),
+                ));
+            }
+        }
+        if let Some(min_drop_percent) = self.min_drop_percent {
+            if !(min_drop_percent > 0.0 && min_drop_percent < 100.0) {
+                errors.push(FieldError::new(
+                    "min_drop_percent",
+                    "Minimum drop must be between 0 and 100 percent".to_string(),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -186,6 +328,113 @@ pub struct LoginDTO {
     pub password: String,
 }
 
+impl LoginDTO {
+    pub fn normalized(self) -> LoginDTO {
+        LoginDTO {
+            login_or_email: self.login_or_email.trim().to_string(),
+            ..self
+        }
+    }
+}
+
+impl Validate for LoginDTO {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        // Logins are never longer than emails, so anything longer can't match a user
+        if self.login_or_email.is_empty() {
+            errors.push(FieldError::new(
+                "login_or_email",
+                "Login or email must not be empty".to_string(),
+            ));
+        } else if self.login_or_email.chars().count() > EMAIL_MAX_LENGTH {
+            errors.push(FieldError::new(
+                "login_or_email",
+                format!(
+                    "Login or email must be at most {} characters long",
+                    EMAIL_MAX_LENGTH
+                ),
+            ));
+        }
+        if self.password.is_empty() {
+            errors.push(FieldError::new(
+                "password",
+                "Password must not be empty".to_string(),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Either the tokens, or a challenge to be completed with a second factor
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResultDTO {
+    Tokens(UserTokensDTO),
+    TwoFactorRequired(TwoFactorChallengeDTO),
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PasswordResetRequestDTO {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PasswordResetConfirmDTO {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserProfileDTO {
+    pub id: i32,
+    pub nickname: Option<String>,
+    pub login: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateProfileDTO {
+    /// An empty nickname removes it
+    pub nickname: Option<String>,
+    pub email: Option<String>,
+}
+
+/// The updated profile, with the verification token if the email changed
+pub type ProfileUpdate = (UserProfileDTO, Option<String>);
+
+#[derive(AsChangeset)]
+#[diesel(table_name = users)]
+struct UserProfileChangeset {
+    nickname: Option<Option<String>>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordDTO {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteAccountDTO {
+    pub password: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct HistoryDTO {
     product_id: i32,
@@ -198,88 +447,604 @@ pub struct HistoryWithProductDTO {
 }
 
 impl User {
-    pub fn signup(conn: &mut PgConnection, user: UserDTO) -> Result<String, String> {
-        if Self::find_user_by_login(conn, &user.login).is_err()
-            && Self::find_user_by_email(conn, &user.email).is_err()
+    /// Expects a normalized and validated user; checks what needs the database and the
+    /// password policy.
+    pub fn signup(
+        conn: &mut PgConnection,
+        user: UserDTO,
+        config: &Config,
+    ) -> QueryResult<Result<User, Vec<FieldError>>> {
+        let mut errors = Vec::new();
+
+        if Self::find_user_by_login(conn, &user.login).is_ok() {
+            errors.push(FieldError::new(
+                "login",
+                format!("Login '{}' is already registered", user.login),
+            ));
+        }
+        if Self::find_user_by_email(conn, &user.email).is_ok() {
+            errors.push(FieldError::new(
+                "email",
+                format!("Email '{}' is already registered", user.email),
+            ));
+        }
+        // Validate password requirements
+        if let Err(message) =
+            config
+                .password_policy
+                .validate(&user.password, &user.login, &user.email)
         {
-            // Validate password requirements
-            let password_requirements = Self::get_password_requirements();
-            if let Err(e) = password_requirements.validate(&user.password) {
-                return Err(e);
-            }
+            errors.push(FieldError::new("password", message));
+        }
 
-            let salt = SaltString::generate(&mut OsRng);
-            let hashed_password = Argon2::default()
-                .hash_password(user.password.as_bytes(), &salt)
-                .expect("Error while hashing password")
-                .to_string();
+        if !errors.is_empty() {
+            return Ok(Err(errors));
+        }
 
-            let user = UserDTO {
-                password: hashed_password,
-                ..user
-            };
+        let user = UserDTO {
+            password: Self::hash_password(&user.password),
+            ..user
+        };
 
-            insert_into(users).values(user).execute(conn).unwrap();
+        let user = match insert_into(users).values(&user).get_result::<User>(conn) {
+            Ok(user) => user,
+            // Someone else signed up with the same login or email in the meantime
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, info)) => {
+                return Ok(Err(
+                    if info.constraint_name() == Some("users_login_lower_uindex") {
+                        vec![FieldError::new(
+                            "login",
+                            format!("Login '{}' is already registered", user.login),
+                        )]
+                    } else {
+                        vec![FieldError::new(
+                            "email",
+                            format!("Email '{}' is already registered", user.email),
+                        )]
+                    },
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        UserNotificationSettings::create_default(conn, user.id)?;
 
-            Ok("Signup successfully".to_string())
-        } else {
-            Err(format!(
-                "Login '{}' or Email '{}' is already registered",
-                &user.login, &user.email
-            ))
-        }
+        Ok(Ok(user))
     }
 
     pub fn login(
         conn: &mut PgConnection,
         login_cred: LoginDTO,
+        client: &ClientInfo,
         config: Data<Config>,
-    ) -> Result<UserTokensDTO, String> {
-        if let Ok(fetched_user) = users
-            .filter(login.eq(&login_cred.login_or_email))
-            .or_filter(email.eq(&login_cred.login_or_email))
-            .get_result::<User>(conn)
-        {
-            if let Ok(parsed_hash) = PasswordHash::new(&fetched_user.password) {
-                if Argon2::default()
-                    .verify_password(login_cred.password.as_bytes(), &parsed_hash)
-                    .is_ok()
-                {
-                    let access_token =
-                        UserToken::generate_access_token(fetched_user.clone(), config);
-                    let refresh_token =
-                        UserToken::generate_refresh_token(conn, fetched_user.clone());
-
-                    let user_tokens = UserTokensDTO {
-                        access_token,
-                        refresh_token,
-                    };
-
-                    return Ok(user_tokens);
-                }
+    ) -> Result<LoginResultDTO, String> {
+        let fetched_user = Self::find_user_by_login_or_email(conn, &login_cred.login_or_email).ok();
+
+        if let Some(ref fetched_user) = fetched_user {
+            if fetched_user.password_matches(&login_cred.password) {
+                return Self::complete_login(conn, fetched_user.clone(), client, config);
             }
         }
+
+        UserAccess::record(conn, fetched_user.map(|user| user.id), false, client).unwrap();
+
         Err("Login, email or password is wrong!".to_string())
     }
 
+    // Finishes a login once the user has proven who they are, asking for the second
+    // factor first if they have one
+    fn complete_login(
+        conn: &mut PgConnection,
+        user: User,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> Result<LoginResultDTO, String> {
+        if user.disabled {
+            return Err("Account is disabled".to_string());
+        }
+
+        // The login only counts as successful once the second factor is checked too
+        if user.totp_enabled {
+            return Ok(LoginResultDTO::TwoFactorRequired(TwoFactorChallengeDTO {
+                challenge_token: TwoFactor::issue_challenge(&user, &config),
+            }));
+        }
+
+        UserAccess::record(conn, Some(user.id), true, client).unwrap();
+
+        Ok(LoginResultDTO::Tokens(UserToken::issue_tokens(
+            conn, user, None, client, config,
+        )))
+    }
+
+    /// Logs in with a validated ID token. The user is found through a linked identity,
+    /// else by the verified email, which links the identity; if there is no such user,
+    /// one is created.
+    pub fn login_oidc(
+        conn: &mut PgConnection,
+        _provider: &str,
+        claims: IdTokenClaims,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> Result<LoginResultDTO, String> {
+        let user = conn
+            .transaction(|conn| {
+                if let Some(identity) = UserIdentity::find(conn, _provider, &claims.sub)? {
+                    return Self::find_user_by_id(conn, identity.user_id).map(Ok);
+                }
+
+                let verified_email = match claims.email {
+                    Some(ref claimed_email) if claims.email_verified => {
+                        normalize_email(claimed_email)
+                    }
+                    _ => {
+                        return Ok(Err(
+                            "The provider did not confirm that your email is verified".to_string(),
+                        ))
+                    }
+                };
+
+                let user = match Self::find_user_by_email(conn, &verified_email).optional()? {
+                    // Otherwise whoever signed up with the address first could take over
+                    // the account of its owner
+                    Some(user) if !user.email_verified => {
+                        return Ok(Err("An account with this email already exists. \
+                             Log in with your password and verify your email to link it"
+                            .to_string()))
+                    }
+                    Some(user) => user,
+                    None => match Self::create_from_identity(
+                        conn,
+                        &verified_email,
+                        claims.preferred_username.as_deref(),
+                    )? {
+                        Ok(user) => user,
+                        Err(message) => return Ok(Err(message)),
+                    },
+                };
+
+                UserIdentity::link(
+                    conn,
+                    UserIdentityInsertable {
+                        user_id: user.id,
+                        provider: _provider.to_string(),
+                        subject: claims.sub.clone(),
+                        email: Some(verified_email),
+                    },
+                )?;
+
+                Ok(Ok(user))
+            })
+            .map_err(|e: diesel::result::Error| e.to_string())??;
+
+        Self::complete_login(conn, user, client, config)
+    }
+
+    // The new user has no usable password; they can set one through a password reset
+    fn create_from_identity(
+        conn: &mut PgConnection,
+        _email: &str,
+        preferred_username: Option<&str>,
+    ) -> QueryResult<Result<User, String>> {
+        if let Some(message) = email_error(_email) {
+            return Ok(Err(message));
+        }
+
+        let login_hint = preferred_username.unwrap_or_else(|| _email.split('@').next().unwrap());
+        let _login = match Self::available_login(conn, login_hint)? {
+            Some(_login) => _login,
+            None => return Ok(Err("Could not find an available login".to_string())),
+        };
+
+        let random_password: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(64)
+            .map(char::from)
+            .collect();
+
+        let user = insert_into(users)
+            .values((
+                login.eq(_login),
+                email.eq(_email),
+                password.eq(Self::hash_password(&random_password)),
+                email_verified.eq(true),
+            ))
+            .get_result::<User>(conn)?;
+        UserNotificationSettings::create_default(conn, user.id)?;
+
+        Ok(Ok(user))
+    }
+
+    // Derives a valid login from the hint, adding a random suffix if it is taken
+    fn available_login(conn: &mut PgConnection, hint: &str) -> QueryResult<Option<String>> {
+        let mut base = hint
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.' || *c == '-')
+            .take(LOGIN_MAX_LENGTH)
+            .collect::<String>();
+        if base.len() < LOGIN_MIN_LENGTH {
+            base = format!("user{}", base);
+        }
+        if Self::find_user_by_login(conn, &base).optional()?.is_none() {
+            return Ok(Some(base));
+        }
+
+        for _ in 0..10 {
+            let suffix = rand::thread_rng().gen_range(1000..10000).to_string();
+            let candidate = format!(
+                "{}{}",
+                &base[..base.len().min(LOGIN_MAX_LENGTH - suffix.len())],
+                suffix
+            );
+            if Self::find_user_by_login(conn, &candidate)
+                .optional()?
+                .is_none()
+            {
+                return Ok(Some(candidate));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Second step of the login for users with 2FA, after the challenge token has been checked
+    pub fn login_two_factor(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        code: &str,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> Result<UserTokensDTO, String> {
+        let user = Self::find_user_by_id(conn, _user_id).map_err(|e| e.to_string())?;
+
+        if user.disabled {
+            return Err("Account is disabled".to_string());
+        }
+
+        if user.totp_enabled && TwoFactor::verify(conn, &user, code).map_err(|e| e.to_string())? {
+            UserAccess::record(conn, Some(user.id), true, client).unwrap();
+
+            return Ok(UserToken::issue_tokens(conn, user, None, client, config));
+        }
+
+        UserAccess::record(conn, Some(user.id), false, client).unwrap();
+
+        Err("Two-factor code is wrong!".to_string())
+    }
+
     pub fn refresh_token(
         conn: &mut PgConnection,
         user_refresh_token: UserRefreshTokenDTO,
+        client: &ClientInfo,
         config: Data<Config>,
-    ) -> Result<UserTokensDTO, String> {
+    ) -> Result<UserTokensDTO, RefreshTokenError> {
         if let Ok(user_token) =
-            UserToken::find_refresh_token(conn, user_refresh_token.refresh_token.clone())
+            UserToken::find_refresh_token(conn, user_refresh_token.refresh_token)
         {
-            let user_tokens = UserToken::refresh_tokens(conn, user_token, config);
-
-            Ok(user_tokens)
+            UserToken::refresh_tokens(conn, user_token, client, config)
         } else {
-            Err("Refresh token not found!".to_string())
+            Err(RefreshTokenError::new("Refresh token not found!"))
         }
     }
 
-    pub fn get_password_requirements() -> PasswordRequirements {
-        PasswordRequirements::default()
+    /// Revokes the session, returning the user who logged out
+    pub fn logout(
+        conn: &mut PgConnection,
+        user_refresh_token: UserRefreshTokenDTO,
+    ) -> Result<i32, String> {
+        UserToken::revoke_refresh_token(conn, user_refresh_token.refresh_token)
+            .map_err(|_| "Refresh token not found!".to_string())
+    }
+
+    pub fn logout_all(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            UserToken::revoke_all_refresh_tokens(conn, _user_id)?;
+
+            // Bumping the version invalidates every access token issued before this point
+            diesel::update(users.filter(users::id.eq(_user_id)))
+                .set(token_version.eq(token_version + 1))
+                .execute(conn)
+        })
+    }
+
+    /// Disabling also signs the user out everywhere
+    pub fn set_disabled(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        _disabled: bool,
+    ) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            if _disabled {
+                Self::logout_all(conn, _user_id)?;
+            }
+
+            diesel::update(users.filter(users::id.eq(_user_id)))
+                .set((disabled.eq(_disabled), updated_date.eq(diesel::dsl::now)))
+                .execute(conn)
+        })
+    }
+
+    pub fn issue_email_verification(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        config: &Config,
+    ) -> QueryResult<String> {
+        UserOneTimeToken::issue(
+            conn,
+            _user_id,
+            TokenPurpose::EmailVerification,
+            config.email_verification_token_expires_in_secs,
+        )
+    }
+
+    /// Returns the id of the verified user
+    pub fn verify_email(conn: &mut PgConnection, token: &str) -> Result<i32, String> {
+        conn.transaction(|conn| {
+            let _user_id = UserOneTimeToken::consume(conn, token, TokenPurpose::EmailVerification)?
+                .ok_or(diesel::result::Error::NotFound)?;
+
+            diesel::update(users.filter(users::id.eq(_user_id)))
+                .set(email_verified.eq(true))
+                .execute(conn)?;
+
+            Ok(_user_id)
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                "Verification token is invalid or has expired".to_string()
+            }
+            e => e.to_string(),
+        })
+    }
+
+    /// Issues a password reset token for the user with this email, if there is one.
+    /// Returns the user's email together with the plaintext token to be mailed.
+    pub fn request_password_reset(
+        conn: &mut PgConnection,
+        reset_request: PasswordResetRequestDTO,
+        config: &Config,
+    ) -> QueryResult<Option<(String, String)>> {
+        match Self::find_user_by_email(conn, &reset_request.email).optional()? {
+            Some(user) => {
+                let token = UserOneTimeToken::issue(
+                    conn,
+                    user.id,
+                    TokenPurpose::PasswordReset,
+                    config.password_reset_token_expires_in_secs,
+                )?;
+                Ok(Some((user.email, token)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn confirm_password_reset(
+        conn: &mut PgConnection,
+        reset_confirm: PasswordResetConfirmDTO,
+        config: &Config,
+    ) -> Result<i32, String> {
+        // Validate before consuming the token, so a weak password doesn't burn it
+        config
+            .password_policy
+            .requirements()
+            .validate(&reset_confirm.new_password)?;
+
+        let mut policy_error = None;
+        conn.transaction(|conn| {
+            let _user_id =
+                UserOneTimeToken::consume(conn, &reset_confirm.token, TokenPurpose::PasswordReset)?
+                    .ok_or(diesel::result::Error::NotFound)?;
+
+            // The rest of the policy needs the user, so on failure the token is put back
+            // by rolling back
+            let user = Self::find_user_by_id(conn, _user_id)?;
+            if let Err(message) = config.password_policy.validate(
+                &reset_confirm.new_password,
+                &user.login,
+                &user.email,
+            ) {
+                policy_error = Some(message);
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+
+            diesel::update(users.filter(users::id.eq(_user_id)))
+                .set((
+                    password.eq(Self::hash_password(&reset_confirm.new_password)),
+                    updated_date.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            // Every existing session could belong to whoever knew the old password
+            Self::logout_all(conn, _user_id)?;
+
+            Ok(_user_id)
+        })
+        .map_err(|e| match (e, policy_error) {
+            (diesel::result::Error::NotFound, _) => {
+                "Reset token is invalid or has expired".to_string()
+            }
+            (diesel::result::Error::RollbackTransaction, Some(message)) => message,
+            (e, _) => e.to_string(),
+        })
+    }
+
+    pub fn get_profile(conn: &mut PgConnection, _user_id: i32) -> QueryResult<UserProfileDTO> {
+        Self::find_user_by_id(conn, _user_id).map(UserProfileDTO::from)
+    }
+
+    /// Updates the nickname and email. A new email has to be verified again, so the
+    /// verification token for it is returned along with the profile.
+    pub fn update_profile(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        profile: UpdateProfileDTO,
+        config: &Config,
+    ) -> QueryResult<Result<ProfileUpdate, Vec<FieldError>>> {
+        let user = Self::find_user_by_id(conn, _user_id)?;
+        let mut errors = Vec::new();
+
+        let new_nickname = match profile.nickname.map(|n| n.trim().to_string()) {
+            Some(n) if n.is_empty() => Some(None),
+            Some(n) if n.chars().count() > NICKNAME_MAX_LENGTH => {
+                errors.push(FieldError::new(
+                    "nickname",
+                    format!(
+                        "Nickname must be at most {} characters long",
+                        NICKNAME_MAX_LENGTH
+                    ),
+                ));
+                None
+            }
+            Some(n) => Some(Some(n)),
+            None => None,
+        };
+
+        let new_email = match profile.email.map(|e| normalize_email(&e)) {
+            Some(e) if e == user.email => None,
+            Some(e) => {
+                if let Some(message) = email_error(&e) {
+                    errors.push(FieldError::new("email", message));
+                } else if Self::find_user_by_email(conn, &e).is_ok() {
+                    errors.push(FieldError::new(
+                        "email",
+                        format!("Email '{}' is already registered", e),
+                    ));
+                }
+                Some(e)
+            }
+            None => None,
+        };
+
+        if !errors.is_empty() {
+            return Ok(Err(errors));
+        }
+
+        let result = conn.transaction(|conn| {
+            let changeset = UserProfileChangeset {
+                nickname: new_nickname,
+                email_verified: new_email.as_ref().map(|_| false),
+                email: new_email.clone(),
+            };
+            let email_changed = changeset.email.is_some();
+
+            let user = diesel::update(users.filter(users::id.eq(_user_id)))
+                .set((changeset, updated_date.eq(diesel::dsl::now)))
+                .get_result::<User>(conn)?;
+
+            let token = if email_changed {
+                Some(Self::issue_email_verification(conn, _user_id, config)?)
+            } else {
+                None
+            };
+
+            Ok((UserProfileDTO::from(user), token))
+        });
+
+        match result {
+            Ok(updated) => Ok(Ok(updated)),
+            // Someone else registered the email in the meantime
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, info))
+                if info.constraint_name() == Some("users_email_lower_uindex") =>
+            {
+                Ok(Err(vec![FieldError::new(
+                    "email",
+                    format!(
+                        "Email '{}' is already registered",
+                        new_email.unwrap_or_default()
+                    ),
+                )]))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Changes the password and signs out every other session. The current session,
+    /// identified by its token family, gets a fresh pair of tokens.
+    pub fn change_password(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        family: String,
+        change: ChangePasswordDTO,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> Result<UserTokensDTO, String> {
+        let user = Self::find_user_by_id(conn, _user_id).map_err(|e| e.to_string())?;
+
+        // Checking the current password counts as a login attempt, so it can't be used
+        // to guess the password around the lockout
+        if !user.password_matches(&change.current_password) {
+            UserAccess::record(conn, Some(user.id), false, client).unwrap();
+            return Err("Current password is wrong!".to_string());
+        }
+        UserAccess::record(conn, Some(user.id), true, client).unwrap();
+
+        config
+            .password_policy
+            .validate(&change.new_password, &user.login, &user.email)?;
+
+        conn.transaction(|conn| {
+            diesel::update(users.filter(users::id.eq(_user_id)))
+                .set((
+                    password.eq(Self::hash_password(&change.new_password)),
+                    updated_date.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Self::logout_all(conn, _user_id)?;
+
+            // Reload the user to sign the new access token with the bumped version
+            let user = Self::find_user_by_id(conn, _user_id)?;
+            Ok(UserToken::issue_tokens(
+                conn,
+                user,
+                Some(family),
+                client,
+                config,
+            ))
+        })
+        .map_err(|e: diesel::result::Error| e.to_string())
+    }
+
+    pub fn delete_account(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        deletion: DeleteAccountDTO,
+    ) -> Result<String, String> {
+        let user = Self::find_user_by_id(conn, _user_id).map_err(|e| e.to_string())?;
+
+        // An access token alone is not enough to wipe the account
+        if !user.password_matches(&deletion.password) {
+            return Err("Password is wrong!".to_string());
+        }
+
+        // Machine clients would silently lose access, so their keys have to be revoked
+        // on purpose first
+        if ApiKey::count_created_by(conn, _user_id).map_err(|e| e.to_string())? > 0 {
+            return Err(
+                "API keys created by this account are still active, revoke them first".to_string(),
+            );
+        }
+
+        UserDataExport::delete_all(conn, _user_id)
+            .map(|_| "Account has been deleted".to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn password_matches(&self, plain_password: &str) -> bool {
+        PasswordHash::new(&self.password)
+            .map(|parsed_hash| {
+                Argon2::default()
+                    .verify_password(plain_password.as_bytes(), &parsed_hash)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn hash_password(plain_password: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(plain_password.as_bytes(), &salt)
+            .expect("Error while hashing password")
+            .to_string()
     }
 
     pub fn add_to_history(
@@ -400,10 +1165,7 @@ impl User {
             .filter(user_subscribed_products::subscribed.eq(true))
             .get_results::<UserSubscribedProduct>(conn)?
             .into_iter()
-            .map(|subscribed_product| UserSubscribedProductDTO {
-                product_id: subscribed_product.product_id,
-                subscribed: subscribed_product.subscribed,
-            })
+            .map(UserSubscribedProductDTO::from)
             .collect::<Vec<UserSubscribedProductDTO>>())
     }
 
@@ -411,11 +1173,25 @@ impl User {
         users.filter(users::id.eq(_id)).get_result::<User>(conn)
     }
 
+    pub fn find_user_by_login_or_email(
+        conn: &mut PgConnection,
+        login_or_email: &str,
+    ) -> QueryResult<User> {
+        users
+            .filter(lower(login).eq(login_or_email.to_lowercase()))
+            .or_filter(lower(email).eq(login_or_email.to_lowercase()))
+            .get_result::<User>(conn)
+    }
+
     pub fn find_user_by_login(conn: &mut PgConnection, _login: &str) -> QueryResult<User> {
-        users.filter(login.eq(_login)).get_result::<User>(conn)
+        users
+            .filter(lower(login).eq(_login.to_lowercase()))
+            .get_result::<User>(conn)
     }
 
     pub fn find_user_by_email(conn: &mut PgConnection, _email: &str) -> QueryResult<User> {
-        users.filter(email.eq(_email)).get_result::<User>(conn)
+        users
+            .filter(lower(email).eq(_email.to_lowercase()))
+            .get_result::<User>(conn)
     }
 }
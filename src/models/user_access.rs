@@ -0,0 +1,212 @@
+use crate::config::app::Config;
+use crate::schema::user_access::{self, dsl::*};
+use actix_web::{web, HttpRequest};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::{count_star, max};
+use diesel::insert_into;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::net::IpAddr;
+use utoipa::ToSchema;
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = user_access)]
+pub struct UserAccess {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub access_date: NaiveDateTime,
+    pub is_successful_login: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_access)]
+pub struct UserAccessInsertable {
+    pub user_id: Option<i32>,
+    pub access_date: NaiveDateTime,
+    pub is_successful_login: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserAccessDTO {
+    pub access_date: NaiveDateTime,
+    pub is_successful_login: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Where a request came from, as recorded in the access log.
+#[derive(Clone)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> ClientInfo {
+        let trusted_proxies = req
+            .app_data::<web::Data<Config>>()
+            .map(|config| config.trusted_proxies.as_slice())
+            .unwrap_or_default();
+
+        ClientInfo {
+            ip_address: req
+                .peer_addr()
+                .map(|addr| client_ip(req, addr.ip(), trusted_proxies).to_string()),
+            user_agent: req
+                .headers()
+                .get(actix_web::http::header::USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string()),
+        }
+    }
+}
+
+// Walks X-Forwarded-For back from the peer through the trusted proxies; anything
+// before the first untrusted address could have been made up by the client
+fn client_ip(req: &HttpRequest, peer: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let forwarded_for = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<Option<IpAddr>>>();
+
+    let mut client = peer;
+    for addr in forwarded_for.into_iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match addr {
+            Some(addr) => client = addr,
+            None => break,
+        }
+    }
+    client
+}
+
+impl UserAccess {
+    pub fn record(
+        conn: &mut PgConnection,
+        _user_id: Option<i32>,
+        successful: bool,
+        client: &ClientInfo,
+    ) -> QueryResult<usize> {
+        insert_into(user_access::table)
+            .values(UserAccessInsertable {
+                user_id: _user_id,
+                // Lockouts are computed in UTC, so don't rely on the database's timezone
+                access_date: Utc::now().naive_utc(),
+                is_successful_login: successful,
+                ip_address: client.ip_address.clone(),
+                user_agent: client.user_agent.clone(),
+            })
+            .execute(conn)
+    }
+
+    pub fn get_access_log(
+        conn: &mut PgConnection,
+        _user_id: i32,
+    ) -> QueryResult<Vec<UserAccessDTO>> {
+        Ok(user_access
+            .select(UserAccess::as_select())
+            .filter(user_id.eq(_user_id))
+            .order_by(access_date.desc())
+            .limit(50) // TODO: make it configurable
+            .get_results(conn)?
+            .into_iter()
+            .map(|access| UserAccessDTO {
+                access_date: access.access_date,
+                is_successful_login: access.is_successful_login,
+                ip_address: access.ip_address,
+                user_agent: access.user_agent,
+            })
+            .collect::<Vec<UserAccessDTO>>())
+    }
+
+    /// Returns the number of seconds the login is still locked for, either because of the
+    /// account's failures since its last successful login or because of the failures coming
+    /// from the client's address.
+    pub fn get_lockout_secs(
+        conn: &mut PgConnection,
+        _user_id: Option<i32>,
+        client: &ClientInfo,
+        config: &Config,
+    ) -> QueryResult<Option<i64>> {
+        let window_start =
+            Utc::now().naive_utc() - Duration::seconds(config.login_failure_window_secs);
+        let mut lockouts = vec![];
+
+        if let Some(_user_id) = _user_id {
+            let last_success = user_access
+                .select(max(access_date))
+                .filter(user_id.eq(_user_id))
+                .filter(is_successful_login.eq(true))
+                .get_result::<Option<NaiveDateTime>>(conn)?;
+            let since = last_success.map_or(window_start, |date| date.max(window_start));
+
+            let (failures, last_failure) = user_access
+                .select((count_star(), max(access_date)))
+                .filter(user_id.eq(_user_id))
+                .filter(is_successful_login.eq(false))
+                .filter(access_date.gt(since))
+                .get_result::<(i64, Option<NaiveDateTime>)>(conn)?;
+
+            lockouts.push(Self::lockout_secs(
+                failures,
+                last_failure,
+                config.login_max_failed_attempts,
+                config,
+            ));
+        }
+
+        if let Some(ref _ip_address) = client.ip_address {
+            let (failures, last_failure) = user_access
+                .select((count_star(), max(access_date)))
+                .filter(ip_address.eq(_ip_address))
+                .filter(is_successful_login.eq(false))
+                .filter(access_date.gt(window_start))
+                .get_result::<(i64, Option<NaiveDateTime>)>(conn)?;
+
+            lockouts.push(Self::lockout_secs(
+                failures,
+                last_failure,
+                config.login_max_failed_attempts_per_ip,
+                config,
+            ));
+        }
+
+        Ok(lockouts.into_iter().flatten().max())
+    }
+
+    // The lockout doubles with every failure past the limit, up to the configured maximum
+    fn lockout_secs(
+        failures: i64,
+        last_failure: Option<NaiveDateTime>,
+        max_failures: i64,
+        config: &Config,
+    ) -> Option<i64> {
+        let last_failure = last_failure?;
+        if failures < max_failures {
+            return None;
+        }
+
+        let exponent = (failures - max_failures).min(30) as u32;
+        let lockout = config
+            .login_lockout_base_secs
+            .saturating_mul(2_i64.pow(exponent))
+            .min(config.login_lockout_max_secs);
+        let remaining =
+            (last_failure + Duration::seconds(lockout) - Utc::now().naive_utc()).num_seconds();
+
+        if remaining > 0 {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::models::role::Role;
+use crate::models::user::{
+    User, UserProductHistory, UserProfileDTO, UserShoppingCart, UserSubscribedProduct,
+};
+use crate::schema::{
+    api_keys, delivered_notifications, user_access, user_identities, user_notification_opt_outs,
+    user_notification_settings, user_one_time_tokens, user_product_history, user_product_review,
+    user_recovery_codes, user_roles, user_settings, user_shopping_carts, user_subscribed_products,
+    user_tokens, users,
+};
+use chrono::{NaiveDateTime, NaiveTime};
+use diesel::prelude::*;
+use serde::Serialize;
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_product_review)]
+pub struct UserProductReview {
+    pub id: i32,
+    pub product_id: i32,
+    pub store_id: i32,
+    pub review_text: Option<String>,
+    pub score: i32,
+    pub published: bool,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_settings)]
+pub struct UserSettingsExport {
+    pub main_country: i32,
+    pub main_city: Option<i32>,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_notification_settings)]
+pub struct UserNotificationSettingsExport {
+    pub email_enabled: bool,
+    pub push_enabled: bool,
+    pub frequency_in_days: i32,
+    pub timezone: String,
+    pub quiet_hours_start: Option<NaiveTime>,
+    pub quiet_hours_end: Option<NaiveTime>,
+    pub instant_price_drops: bool,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_notification_opt_outs)]
+pub struct UserNotificationOptOutExport {
+    pub channel: String,
+    pub notification_type: String,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_identities)]
+pub struct UserIdentityExport {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_access)]
+pub struct UserAccessExport {
+    pub access_date: NaiveDateTime,
+    pub is_successful_login: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// A refresh token; the token itself is only stored as a hash and left out
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = user_tokens)]
+pub struct UserSessionExport {
+    pub created_date: NaiveDateTime,
+    pub last_used_date: Option<NaiveDateTime>,
+    pub expiration_date: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = delivered_notifications)]
+pub struct DeliveredNotificationExport {
+    pub subscribe_id: i32,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub store_id: Option<i32>,
+    pub old_price: Option<f32>,
+    pub new_price: Option<f32>,
+    pub created_date: NaiveDateTime,
+    pub delivered: bool,
+    pub sent_channels: Vec<String>,
+    pub read_date: Option<NaiveDateTime>,
+}
+
+/// An API key the user created, without the key or any part of it
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKeyExport {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_date: NaiveDateTime,
+    pub last_used_date: Option<NaiveDateTime>,
+}
+
+/// Everything stored about a user, as handed out on a personal data request.
+#[derive(Serialize)]
+pub struct UserDataExport {
+    pub profile: UserProfileDTO,
+    pub roles: Vec<String>,
+    pub identities: Vec<UserIdentityExport>,
+    pub settings: Option<UserSettingsExport>,
+    pub notification_settings: Option<UserNotificationSettingsExport>,
+    pub notification_opt_outs: Vec<UserNotificationOptOutExport>,
+    pub history: Vec<UserProductHistory>,
+    pub shopping_cart: Vec<UserShoppingCart>,
+    pub subscriptions: Vec<UserSubscribedProduct>,
+    pub reviews: Vec<UserProductReview>,
+    pub access_log: Vec<UserAccessExport>,
+    pub sessions: Vec<UserSessionExport>,
+    pub notifications: Vec<DeliveredNotificationExport>,
+    pub api_keys: Vec<ApiKeyExport>,
+}
+
+impl UserDataExport {
+    pub fn collect(conn: &mut PgConnection, _user_id: i32) -> QueryResult<UserDataExport> {
+        Ok(UserDataExport {
+            profile: User::get_profile(conn, _user_id)?,
+            roles: Role::get_user_role_names(conn, _user_id)?,
+            identities: user_identities::table
+                .select(UserIdentityExport::as_select())
+                .filter(user_identities::user_id.eq(_user_id))
+                .get_results(conn)?,
+            settings: user_settings::table
+                .select(UserSettingsExport::as_select())
+                .filter(user_settings::user_id.eq(_user_id))
+                .get_result(conn)
+                .optional()?,
+            notification_settings: user_notification_settings::table
+                .select(UserNotificationSettingsExport::as_select())
+                .filter(user_notification_settings::user_id.eq(_user_id))
+                .get_result(conn)
+                .optional()?,
+            notification_opt_outs: user_notification_opt_outs::table
+                .select(UserNotificationOptOutExport::as_select())
+                .filter(user_notification_opt_outs::user_id.eq(_user_id))
+                .get_results(conn)?,
+            history: user_product_history::table
+                .select(UserProductHistory::as_select())
+                .filter(user_product_history::user_id.eq(_user_id))
+                .order_by(user_product_history::created_date)
+                .get_results(conn)?,
+            shopping_cart: user_shopping_carts::table
+                .select(UserShoppingCart::as_select())
+                .filter(user_shopping_carts::user_id.eq(_user_id))
+                .get_results(conn)?,
+            subscriptions: user_subscribed_products::table
+                .select(UserSubscribedProduct::as_select())
+                .filter(user_subscribed_products::user_id.eq(_user_id))
+                .get_results(conn)?,
+            reviews: user_product_review::table
+                .select(UserProductReview::as_select())
+                .filter(user_product_review::user_id.eq(_user_id))
+                .get_results(conn)?,
+            access_log: user_access::table
+                .select(UserAccessExport::as_select())
+                .filter(user_access::user_id.eq(_user_id))
+                .order_by(user_access::access_date)
+                .get_results(conn)?,
+            sessions: user_tokens::table
+                .select(UserSessionExport::as_select())
+                .filter(user_tokens::user_id.eq(_user_id))
+                .order_by(user_tokens::created_date)
+                .get_results(conn)?,
+            notifications: delivered_notifications::table
+                .inner_join(user_subscribed_products::table)
+                .select(DeliveredNotificationExport::as_select())
+                .filter(user_subscribed_products::user_id.eq(_user_id))
+                .order_by(delivered_notifications::created_date)
+                .get_results(conn)?,
+            api_keys: api_keys::table
+                .select(ApiKeyExport::as_select())
+                .filter(api_keys::created_by.eq(_user_id))
+                .order_by(api_keys::created_date)
+                .get_results(conn)?,
+        })
+    }
+
+    /// Deletes the user and every row referencing them. Nothing is kept in anonymised
+    /// form, since the remaining tables only hold data about products and stores.
+    /// API keys the user created aren't revoked; deleting fails while there are any.
+    pub fn delete_all(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        conn.transaction(|conn| {
+            let subscription_ids = user_subscribed_products::table
+                .select(user_subscribed_products::id)
+                .filter(user_subscribed_products::user_id.eq(_user_id));
+
+            diesel::delete(
+                delivered_notifications::table
+                    .filter(delivered_notifications::subscribe_id.eq_any(subscription_ids)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                user_subscribed_products::table
+                    .filter(user_subscribed_products::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                user_shopping_carts::table.filter(user_shopping_carts::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                user_product_history::table.filter(user_product_history::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                user_product_review::table.filter(user_product_review::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(user_access::table.filter(user_access::user_id.eq(_user_id)))
+                .execute(conn)?;
+            diesel::delete(user_settings::table.filter(user_settings::user_id.eq(_user_id)))
+                .execute(conn)?;
+            diesel::delete(
+                user_notification_opt_outs::table
+                    .filter(user_notification_opt_outs::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                user_notification_settings::table
+                    .filter(user_notification_settings::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(user_roles::table.filter(user_roles::user_id.eq(_user_id)))
+                .execute(conn)?;
+            diesel::delete(user_tokens::table.filter(user_tokens::user_id.eq(_user_id)))
+                .execute(conn)?;
+            diesel::delete(
+                user_one_time_tokens::table.filter(user_one_time_tokens::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(user_identities::table.filter(user_identities::user_id.eq(_user_id)))
+                .execute(conn)?;
+            diesel::delete(
+                user_recovery_codes::table.filter(user_recovery_codes::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(users::table.filter(users::id.eq(_user_id))).execute(conn)
+        })
+    }
+}
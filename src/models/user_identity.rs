@@ -0,0 +1,58 @@
+use crate::schema::user_identities::{self, dsl::*};
+use chrono::NaiveDateTime;
+use diesel::insert_into;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A link between a local user and their account at an OpenID Connect provider
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = user_identities)]
+pub struct UserIdentity {
+    pub id: i32,
+    pub user_id: i32,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_identities)]
+pub struct UserIdentityInsertable {
+    pub user_id: i32,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OidcAuthorizationDTO {
+    /// Where to send the user to log in at the provider
+    pub authorization_url: String,
+}
+
+/// The query parameters the provider redirected back with
+#[derive(Deserialize, ToSchema)]
+pub struct OidcCallbackDTO {
+    pub state: String,
+    pub code: String,
+}
+
+impl UserIdentity {
+    pub fn find(
+        conn: &mut PgConnection,
+        _provider: &str,
+        _subject: &str,
+    ) -> QueryResult<Option<UserIdentity>> {
+        user_identities
+            .filter(provider.eq(_provider))
+            .filter(subject.eq(_subject))
+            .get_result::<UserIdentity>(conn)
+            .optional()
+    }
+
+    pub fn link(conn: &mut PgConnection, identity: UserIdentityInsertable) -> QueryResult<usize> {
+        insert_into(user_identities).values(identity).execute(conn)
+    }
+}
@@ -0,0 +1,397 @@
+use crate::models::validation::{FieldError, Validate};
+use crate::schema::user_notification_opt_outs;
+use crate::schema::user_notification_settings::{self, dsl::*};
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::insert_into;
+use diesel::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+
+// Digests are sent at least once a month
+pub const FREQUENCY_IN_DAYS_MAX: i32 = 30;
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = user_notification_settings)]
+#[diesel(treat_none_as_null = true)]
+pub struct UserNotificationSettings {
+    pub user_id: i32,
+    pub email_enabled: bool,
+    pub push_enabled: bool,
+    pub frequency_in_days: i32,
+    pub timezone: String,
+    pub quiet_hours_start: Option<NaiveTime>,
+    pub quiet_hours_end: Option<NaiveTime>,
+    pub instant_price_drops: bool,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_notification_opt_outs)]
+pub struct UserNotificationOptOut {
+    pub user_id: i32,
+    pub channel: String,
+    pub notification_type: String,
+}
+
+/// A way of reaching the user
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationChannelKind {
+    Email,
+    Push,
+}
+
+/// What the user is notified about
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationType {
+    /// The price of a subscribed product dropped
+    PriceDrop,
+    /// A summary of the price changes since the last one
+    Digest,
+}
+
+impl NotificationChannelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannelKind::Email => "email",
+            NotificationChannelKind::Push => "push",
+        }
+    }
+
+    fn parse(value: &str) -> Option<NotificationChannelKind> {
+        match value {
+            "email" => Some(NotificationChannelKind::Email),
+            "push" => Some(NotificationChannelKind::Push),
+            _ => None,
+        }
+    }
+}
+
+impl NotificationType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationType::PriceDrop => "price_drop",
+            NotificationType::Digest => "digest",
+        }
+    }
+
+    fn parse(value: &str) -> Option<NotificationType> {
+        match value {
+            "price_drop" => Some(NotificationType::PriceDrop),
+            "digest" => Some(NotificationType::Digest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct NotificationOptOutDTO {
+    pub channel: NotificationChannelKind,
+    pub notification_type: NotificationType,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UserNotificationSettingsDTO {
+    pub email_enabled: bool,
+    pub push_enabled: bool,
+    /// How often digests are sent, from 1 to 30 days
+    pub frequency_in_days: i32,
+    /// Whether price drops are sent right away rather than collected into the digest, true
+    /// by default
+    #[serde(default = "instant_price_drops_default")]
+    pub instant_price_drops: bool,
+    /// An IANA time zone, used to interpret the quiet hours
+    #[schema(example = "Europe/Warsaw")]
+    pub timezone: String,
+    /// Nothing is sent between the start and the end of the quiet hours, which may span
+    /// midnight. Both must be set, or neither.
+    #[serde(default, deserialize_with = "deserialize_time_of_day")]
+    #[schema(value_type = Option<String>, example = "22:00")]
+    pub quiet_hours_start: Option<NaiveTime>,
+    #[serde(default, deserialize_with = "deserialize_time_of_day")]
+    #[schema(value_type = Option<String>, example = "07:00")]
+    pub quiet_hours_end: Option<NaiveTime>,
+    /// Notification types the user doesn't want on a channel, even when it is enabled
+    #[serde(default)]
+    pub opt_outs: Vec<NotificationOptOutDTO>,
+}
+
+// Accepts "HH:MM" as well as "HH:MM:SS"
+fn deserialize_time_of_day<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) => NaiveTime::parse_from_str(&value, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M:%S"))
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid time of day '{}'", value))),
+        None => Ok(None),
+    }
+}
+
+fn instant_price_drops_default() -> bool {
+    true
+}
+
+pub struct DigestState {
+    pub last_digest_date: Option<NaiveDateTime>,
+    /// Until when another instance is sending the digest
+    pub lease_until: Option<NaiveDateTime>,
+}
+
+impl Validate for UserNotificationSettingsDTO {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if !(1..=FREQUENCY_IN_DAYS_MAX).contains(&self.frequency_in_days) {
+            errors.push(FieldError::new(
+                "frequency_in_days",
+                format!(
+                    "Frequency must be between 1 and {} days",
+                    FREQUENCY_IN_DAYS_MAX
+                ),
+            ));
+        }
+        if self.timezone.parse::<Tz>().is_err() {
+            errors.push(FieldError::new(
+                "timezone",
+                format!("Unknown time zone '{}'", self.timezone),
+            ));
+        }
+        match (self.quiet_hours_start, self.quiet_hours_end) {
+            (Some(start), Some(end)) if start == end => errors.push(FieldError::new(
+                "quiet_hours_end",
+                "Quiet hours must end at a different time than they start".to_string(),
+            )),
+            (Some(_), None) => errors.push(FieldError::new(
+                "quiet_hours_end",
+                "Quiet hours need an end".to_string(),
+            )),
+            (None, Some(_)) => errors.push(FieldError::new(
+                "quiet_hours_start",
+                "Quiet hours need a start".to_string(),
+            )),
+            _ => {}
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl UserNotificationSettingsDTO {
+    /// Whether the user wants notifications of the type on the channel. Price drops aren't
+    /// wanted on their own by users who turned instant price drops off.
+    pub fn allows(&self, channel: NotificationChannelKind, _type: NotificationType) -> bool {
+        let enabled = match channel {
+            NotificationChannelKind::Email => self.email_enabled,
+            NotificationChannelKind::Push => self.push_enabled,
+        };
+
+        enabled
+            && (_type != NotificationType::PriceDrop || self.instant_price_drops)
+            && !self.opt_outs.contains(&NotificationOptOutDTO {
+                channel,
+                notification_type: _type,
+            })
+    }
+
+    /// When the quiet hours end, if they are on at the given time
+    pub fn quiet_hours_end(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (start, end) = (self.quiet_hours_start?, self.quiet_hours_end?);
+        // Settings are validated, but fall back to UTC rather than never notifying
+        let tz = self.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        let local = at.with_timezone(&tz);
+        let time = local.time();
+
+        let end_date = if start < end {
+            if time < start || time >= end {
+                return None;
+            }
+            local.date_naive()
+        } else if time >= start {
+            local.date_naive() + Duration::days(1)
+        } else if time < end {
+            local.date_naive()
+        } else {
+            return None;
+        };
+
+        let end = end_date.and_time(end);
+        // The end may fall into a daylight saving gap
+        tz.from_local_datetime(&end)
+            .earliest()
+            .or_else(|| {
+                tz.from_local_datetime(&(end + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|end| end.with_timezone(&Utc))
+    }
+}
+
+impl UserNotificationSettings {
+    /// Creates the default settings, unless the user already has some
+    pub fn create_default(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        insert_into(user_notification_settings)
+            .values(user_id.eq(_user_id))
+            .on_conflict_do_nothing()
+            .execute(conn)
+    }
+
+    fn find(conn: &mut PgConnection, _user_id: i32) -> QueryResult<UserNotificationSettings> {
+        user_notification_settings
+            .select(UserNotificationSettings::as_select())
+            .filter(user_id.eq(_user_id))
+            .get_result(conn)
+    }
+
+    pub fn get(conn: &mut PgConnection, _user_id: i32) -> QueryResult<UserNotificationSettingsDTO> {
+        // Users who signed up before the settings were created at signup get the defaults
+        let settings = match Self::find(conn, _user_id).optional()? {
+            Some(settings) => settings,
+            None => {
+                Self::create_default(conn, _user_id)?;
+                Self::find(conn, _user_id)?
+            }
+        };
+        let opt_outs = Self::get_opt_outs(conn, _user_id)?;
+
+        Ok(UserNotificationSettingsDTO {
+            email_enabled: settings.email_enabled,
+            push_enabled: settings.push_enabled,
+            frequency_in_days: settings.frequency_in_days,
+            instant_price_drops: settings.instant_price_drops,
+            timezone: settings.timezone,
+            quiet_hours_start: settings.quiet_hours_start,
+            quiet_hours_end: settings.quiet_hours_end,
+            opt_outs,
+        })
+    }
+
+    pub fn get_opt_outs(
+        conn: &mut PgConnection,
+        _user_id: i32,
+    ) -> QueryResult<Vec<NotificationOptOutDTO>> {
+        Ok(user_notification_opt_outs::table
+            .select(UserNotificationOptOut::as_select())
+            .filter(user_notification_opt_outs::user_id.eq(_user_id))
+            .order_by((
+                user_notification_opt_outs::channel,
+                user_notification_opt_outs::notification_type,
+            ))
+            .get_results(conn)?
+            .into_iter()
+            // Skip anything a newer version stored that this one doesn't know
+            .filter_map(|opt_out| {
+                Some(NotificationOptOutDTO {
+                    channel: NotificationChannelKind::parse(&opt_out.channel)?,
+                    notification_type: NotificationType::parse(&opt_out.notification_type)?,
+                })
+            })
+            .collect())
+    }
+
+    /// Replaces the settings and opt-outs; expects validated settings
+    pub fn update(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        settings: UserNotificationSettingsDTO,
+    ) -> QueryResult<UserNotificationSettingsDTO> {
+        let row = UserNotificationSettings {
+            user_id: _user_id,
+            email_enabled: settings.email_enabled,
+            push_enabled: settings.push_enabled,
+            frequency_in_days: settings.frequency_in_days,
+            instant_price_drops: settings.instant_price_drops,
+            timezone: settings.timezone,
+            quiet_hours_start: settings.quiet_hours_start,
+            quiet_hours_end: settings.quiet_hours_end,
+        };
+        let opt_outs = settings
+            .opt_outs
+            .iter()
+            .map(|opt_out| UserNotificationOptOut {
+                user_id: _user_id,
+                channel: opt_out.channel.as_str().to_string(),
+                notification_type: opt_out.notification_type.as_str().to_string(),
+            })
+            .collect::<Vec<UserNotificationOptOut>>();
+
+        conn.transaction(|conn| {
+            insert_into(user_notification_settings)
+                .values(&row)
+                .on_conflict(user_id)
+                .do_update()
+                .set(&row)
+                .execute(conn)?;
+
+            diesel::delete(
+                user_notification_opt_outs::table
+                    .filter(user_notification_opt_outs::user_id.eq(_user_id)),
+            )
+            .execute(conn)?;
+            insert_into(user_notification_opt_outs::table)
+                .values(&opt_outs)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            Self::get(conn, _user_id)
+        })
+    }
+
+    /// Locks the user's settings while their digest is claimed, and returns when the last
+    /// one was sent. Nothing if another instance is claiming it.
+    pub fn lock_for_digest(
+        conn: &mut PgConnection,
+        _user_id: i32,
+    ) -> QueryResult<Option<DigestState>> {
+        user_notification_settings
+            .select((last_digest_date, digest_lease_until))
+            .filter(user_id.eq(_user_id))
+            .for_update()
+            .skip_locked()
+            .get_result::<(Option<NaiveDateTime>, Option<NaiveDateTime>)>(conn)
+            .optional()
+            .map(|state| {
+                state.map(|(_last_digest_date, _lease_until)| DigestState {
+                    last_digest_date: _last_digest_date,
+                    lease_until: _lease_until,
+                })
+            })
+    }
+
+    /// Keeps other instances from sending the digest until the lease runs out
+    pub fn lease_digest(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        until: NaiveDateTime,
+    ) -> QueryResult<usize> {
+        diesel::update(user_notification_settings.filter(user_id.eq(_user_id)))
+            .set(digest_lease_until.eq(until))
+            .execute(conn)
+    }
+
+    /// Ends the lease, recording when the digest was sent if it was
+    pub fn record_digest(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        sent_date: Option<NaiveDateTime>,
+    ) -> QueryResult<usize> {
+        let settings = user_notification_settings.filter(user_id.eq(_user_id));
+        match sent_date {
+            Some(sent_date) => diesel::update(settings)
+                .set((
+                    last_digest_date.eq(sent_date),
+                    digest_lease_until.eq(None::<NaiveDateTime>),
+                ))
+                .execute(conn),
+            None => diesel::update(settings)
+                .set(digest_lease_until.eq(None::<NaiveDateTime>))
+                .execute(conn),
+        }
+    }
+}
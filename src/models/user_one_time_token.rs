@@ -0,0 +1,101 @@
+use crate::models::user_tokens::UserToken;
+use crate::schema::user_one_time_tokens::{self, dsl::*};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::insert_into;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+#[derive(Clone, Copy)]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailVerification => "email_verification",
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = user_one_time_tokens)]
+pub struct UserOneTimeToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub purpose: String,
+    pub token_hash: String,
+    pub expiration_date: NaiveDateTime,
+    pub used_date: Option<NaiveDateTime>,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_one_time_tokens)]
+pub struct UserOneTimeTokenInsertable {
+    pub user_id: i32,
+    pub purpose: String,
+    pub token_hash: String,
+    pub expiration_date: NaiveDateTime,
+}
+
+impl UserOneTimeToken {
+    /// Issues a new token for `purpose`, invalidating the user's previous unused ones.
+    /// Only the hash is stored; the plaintext is returned to be sent to the user.
+    pub fn issue(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        _purpose: TokenPurpose,
+        expires_in_secs: i64,
+    ) -> QueryResult<String> {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(48)
+            .map(char::from)
+            .collect();
+
+        diesel::delete(
+            user_one_time_tokens
+                .filter(user_id.eq(_user_id))
+                .filter(purpose.eq(_purpose.as_str()))
+                .filter(used_date.is_null()),
+        )
+        .execute(conn)?;
+
+        insert_into(user_one_time_tokens)
+            .values(UserOneTimeTokenInsertable {
+                user_id: _user_id,
+                purpose: _purpose.as_str().to_string(),
+                token_hash: UserToken::hash_token(&token),
+                expiration_date: Utc::now().naive_utc() + Duration::seconds(expires_in_secs),
+            })
+            .execute(conn)?;
+
+        Ok(token)
+    }
+
+    /// Marks the token as used and returns its owner, or `None` if the token is unknown,
+    /// expired or was already used.
+    pub fn consume(
+        conn: &mut PgConnection,
+        token: &str,
+        _purpose: TokenPurpose,
+    ) -> QueryResult<Option<i32>> {
+        let now = Utc::now().naive_utc();
+
+        diesel::update(
+            user_one_time_tokens
+                .filter(token_hash.eq(UserToken::hash_token(token)))
+                .filter(purpose.eq(_purpose.as_str()))
+                .filter(used_date.is_null())
+                .filter(expiration_date.gt(now)),
+        )
+        .set(used_date.eq(now))
+        .returning(user_id)
+        .get_result::<i32>(conn)
+        .optional()
+    }
+}
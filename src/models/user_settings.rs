@@ -0,0 +1,163 @@
+use crate::models::store::StoreScope;
+use crate::models::validation::FieldError;
+use crate::schema::user_settings::{self, dsl::*};
+use crate::schema::{cities, countries, regions};
+use diesel::insert_into;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = user_settings)]
+#[diesel(treat_none_as_null = true)]
+pub struct UserSettings {
+    pub user_id: i32,
+    pub main_country: i32,
+    pub main_city: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UserSettingsDTO {
+    pub main_country: i32,
+    /// Must be in the main country
+    pub main_city: Option<i32>,
+}
+
+impl From<UserSettings> for UserSettingsDTO {
+    fn from(settings: UserSettings) -> Self {
+        UserSettingsDTO {
+            main_country: settings.main_country,
+            main_city: settings.main_city,
+        }
+    }
+}
+
+/// Which stores' prices to show
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceScope {
+    /// Stores in the user's home city
+    City,
+    /// Stores in the user's home country
+    Country,
+    /// All stores
+    All,
+}
+
+impl UserSettings {
+    pub fn get(conn: &mut PgConnection, _user_id: i32) -> QueryResult<Option<UserSettings>> {
+        user_settings
+            .select(UserSettings::as_select())
+            .filter(user_id.eq(_user_id))
+            .get_result(conn)
+            .optional()
+    }
+
+    pub fn update(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        settings: UserSettingsDTO,
+    ) -> QueryResult<Result<UserSettingsDTO, Vec<FieldError>>> {
+        let settings = UserSettings {
+            user_id: _user_id,
+            main_country: settings.main_country,
+            main_city: settings.main_city,
+        };
+
+        if let Err(errors) = Self::validate(conn, &settings)? {
+            return Ok(Err(errors));
+        }
+
+        insert_into(user_settings)
+            .values(&settings)
+            .on_conflict(user_id)
+            .do_update()
+            .set(&settings)
+            .get_result::<UserSettings>(conn)
+            .map(|settings| Ok(UserSettingsDTO::from(settings)))
+    }
+
+    fn validate(
+        conn: &mut PgConnection,
+        settings: &UserSettings,
+    ) -> QueryResult<Result<(), Vec<FieldError>>> {
+        let country_exists = diesel::select(diesel::dsl::exists(
+            countries::table.filter(countries::id.eq(settings.main_country)),
+        ))
+        .get_result::<bool>(conn)?;
+        if !country_exists {
+            return Ok(Err(vec![FieldError::new(
+                "main_country",
+                format!("Country {} does not exist", settings.main_country),
+            )]));
+        }
+
+        if let Some(city_id) = settings.main_city {
+            let city_country = cities::table
+                .inner_join(regions::table)
+                .select(regions::country_id)
+                .filter(cities::id.eq(city_id))
+                .get_result::<i32>(conn)
+                .optional()?;
+            match city_country {
+                None => {
+                    return Ok(Err(vec![FieldError::new(
+                        "main_city",
+                        format!("City {} does not exist", city_id),
+                    )]))
+                }
+                Some(city_country) if city_country != settings.main_country => {
+                    return Ok(Err(vec![FieldError::new(
+                        "main_city",
+                        format!(
+                            "City {} is not in country {}",
+                            city_id, settings.main_country
+                        ),
+                    )]))
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// The stores to show prices from. By default that's the user's city, or their
+    /// country if they haven't picked one; anonymous users and users without settings
+    /// see every store unless they ask for a scope.
+    pub fn store_scope(
+        conn: &mut PgConnection,
+        _user_id: Option<i32>,
+        scope: Option<PriceScope>,
+    ) -> Result<Option<StoreScope>, String> {
+        if let Some(PriceScope::All) = scope {
+            return Ok(None);
+        }
+
+        let settings = match _user_id {
+            Some(_user_id) => Self::get(conn, _user_id).map_err(|e| e.to_string())?,
+            None => None,
+        };
+
+        match (scope, settings) {
+            (None, None) => Ok(None),
+            (Some(_), None) => {
+                Err("Set your home country in the settings to scope prices".to_string())
+            }
+            (
+                Some(PriceScope::City),
+                Some(UserSettings {
+                    main_city: None, ..
+                }),
+            ) => Err("Set your home city in the settings to scope prices to it".to_string()),
+            (Some(PriceScope::Country), Some(settings)) => Ok(Some(StoreScope {
+                country_id: settings.main_country,
+                city_id: None,
+            })),
+            (_, Some(settings)) => Ok(Some(StoreScope {
+                country_id: settings.main_country,
+                city_id: settings.main_city,
+            })),
+        }
+    }
+}
@@ -1,28 +1,43 @@
 use crate::config::app::Config;
 use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::models::role::Role;
 use crate::models::user::User;
+use crate::models::user_access::ClientInfo;
 use crate::schema::user_tokens::{self, dsl::*};
 use actix_web::web::Data;
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use diesel::prelude::*;
-use jsonwebtoken::EncodingKey;
+use log::warn;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use utoipa::ToSchema;
 
 #[derive(Identifiable, Queryable)]
 pub struct UserToken {
     pub id: i32,
     pub user_id: i32,
-    pub refresh_token: String,
+    pub token_hash: String,
+    pub family_id: String,
+    pub expiration_date: NaiveDateTime,
+    pub rotated: bool,
+    pub created_date: NaiveDateTime,
+    pub last_used_date: Option<NaiveDateTime>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
 #[diesel(table_name = user_tokens)]
 pub struct UserTokenInsertable {
     pub user_id: i32,
-    pub refresh_token: String,
+    pub token_hash: String,
+    pub family_id: String,
+    pub expiration_date: NaiveDateTime,
+    pub created_date: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -36,9 +51,72 @@ pub struct UserRefreshTokenDTO {
     pub refresh_token: String,
 }
 
+/// A logged in device, i.e. a refresh token family
+#[derive(Serialize, ToSchema)]
+pub struct SessionDTO {
+    pub id: String,
+    pub created_date: NaiveDateTime,
+    pub last_used_date: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether this is the session the request was made from
+    pub current: bool,
+}
+
+/// Why a refresh token was refused. On reuse the whole session is revoked, and the user
+/// it belonged to is given so that their cached sessions can be dropped.
+pub struct RefreshTokenError {
+    pub message: String,
+    pub revoked_user_id: Option<i32>,
+}
+
+impl RefreshTokenError {
+    pub fn new(message: &str) -> RefreshTokenError {
+        RefreshTokenError {
+            message: message.to_string(),
+            revoked_user_id: None,
+        }
+    }
+}
+
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
 impl UserToken {
-    pub fn generate_access_token(user: User, config: Data<Config>) -> String {
+    pub fn hash_token(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Issues an access and a refresh token for the session `family`,
+    /// or for a new session when `family` is `None`.
+    pub fn issue_tokens(
+        conn: &mut PgConnection,
+        user: User,
+        family: Option<String>,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> UserTokensDTO {
+        let family = family.unwrap_or_else(|| random_string(32));
+
+        UserTokensDTO {
+            access_token: Self::generate_access_token(conn, user.clone(), &family, config.clone()),
+            refresh_token: Self::generate_refresh_token(conn, user, family, client, config),
+        }
+    }
+
+    pub fn generate_access_token(
+        conn: &mut PgConnection,
+        user: User,
+        family: &str,
+        config: Data<Config>,
+    ) -> String {
         let now = Utc::now();
+        let user_roles = Role::get_user_role_names(conn, user.id).unwrap();
 
         let token_claims = TokenClaims {
             sub: user.id.to_string(),
@@ -46,29 +124,44 @@ impl UserToken {
             exp: (now + Duration::seconds(config.jwt_expires_in_secs as i64)).timestamp() as usize,
             nickname: user.nickname,
             login: user.login,
-            roles: vec![],
+            roles: user_roles,
+            ver: user.token_version,
+            sid: family.to_string(),
         };
 
-        let token = jsonwebtoken::encode(
-            &jsonwebtoken::Header::default(),
-            &token_claims,
-            &EncodingKey::from_secret(config.jwt_secret.as_ref()),
-        )
-        .unwrap();
-
-        token
+        config.jwt_keys.encode(&token_claims).unwrap()
     }
 
-    pub fn generate_refresh_token(conn: &mut PgConnection, user: User) -> String {
-        let token: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(64)
-            .map(char::from)
-            .collect();
+    /// Issues a new refresh token in the token family. Only its hash is stored;
+    /// the plaintext is returned to the caller once.
+    pub fn generate_refresh_token(
+        conn: &mut PgConnection,
+        user: User,
+        family: String,
+        client: &ClientInfo,
+        config: Data<Config>,
+    ) -> String {
+        let token = random_string(64);
+        let now = Utc::now().naive_utc();
+
+        // Expired tokens are of no use even for reuse detection
+        diesel::delete(
+            user_tokens
+                .filter(user_id.eq(user.id))
+                .filter(expiration_date.lt(now)),
+        )
+        .execute(conn)
+        .unwrap();
 
         let user_token = UserTokenInsertable {
             user_id: user.id,
-            refresh_token: token.clone(),
+            token_hash: Self::hash_token(&token),
+            family_id: family,
+            expiration_date: now + Duration::seconds(config.refresh_token_expires_in_secs),
+            // Compared with the UTC last-used date, so don't rely on the database's timezone
+            created_date: now,
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
         };
         diesel::insert_into(user_tokens)
             .values(user_token)
@@ -80,30 +173,134 @@ impl UserToken {
 
     pub fn find_refresh_token(conn: &mut PgConnection, token: String) -> QueryResult<UserToken> {
         user_tokens
-            .filter(refresh_token.eq(token))
+            .filter(token_hash.eq(Self::hash_token(&token)))
             .get_result::<UserToken>(conn)
     }
 
+    /// Revokes the session the token belongs to, returning the user it was issued to
+    pub fn revoke_refresh_token(conn: &mut PgConnection, token: String) -> QueryResult<i32> {
+        let user_token = Self::find_refresh_token(conn, token)?;
+        Self::revoke_family(conn, &user_token.family_id)?;
+
+        Ok(user_token.user_id)
+    }
+
+    pub fn revoke_family(conn: &mut PgConnection, _family_id: &str) -> QueryResult<usize> {
+        diesel::delete(user_tokens.filter(family_id.eq(_family_id))).execute(conn)
+    }
+
+    pub fn revoke_all_refresh_tokens(conn: &mut PgConnection, _user_id: i32) -> QueryResult<usize> {
+        diesel::delete(user_tokens.filter(user_id.eq(_user_id))).execute(conn)
+    }
+
+    /// Lists the user's sessions, newest activity first. A session's id is its family id.
+    pub fn get_sessions(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        current_family: &str,
+    ) -> QueryResult<Vec<SessionDTO>> {
+        let tokens = user_tokens
+            .filter(user_id.eq(_user_id))
+            .filter(expiration_date.gt(Utc::now().naive_utc()))
+            .order_by(user_tokens::created_date)
+            .get_results::<UserToken>(conn)?;
+
+        let mut sessions: Vec<SessionDTO> = vec![];
+        for token in tokens {
+            let last_used = token.last_used_date.unwrap_or(token.created_date);
+
+            match sessions
+                .iter_mut()
+                .find(|session| session.id == token.family_id)
+            {
+                // Tokens are ordered by creation, so the later one holds the latest client info
+                Some(session) => {
+                    session.last_used_date = session.last_used_date.max(last_used);
+                    session.ip_address = token.ip_address.or(session.ip_address.take());
+                    session.user_agent = token.user_agent.or(session.user_agent.take());
+                }
+                None => sessions.push(SessionDTO {
+                    current: token.family_id == current_family,
+                    id: token.family_id,
+                    created_date: token.created_date,
+                    last_used_date: last_used,
+                    ip_address: token.ip_address,
+                    user_agent: token.user_agent,
+                }),
+            }
+        }
+
+        sessions.sort_by(|a, b| b.last_used_date.cmp(&a.last_used_date));
+
+        Ok(sessions)
+    }
+
+    /// The ids of the user's sessions, i.e. the families of their refresh tokens
+    pub fn session_ids(conn: &mut PgConnection, _user_id: i32) -> QueryResult<Vec<String>> {
+        user_tokens
+            .select(family_id)
+            .filter(user_id.eq(_user_id))
+            .distinct()
+            .load::<String>(conn)
+    }
+
+    pub fn revoke_session(
+        conn: &mut PgConnection,
+        _user_id: i32,
+        _family_id: &str,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            user_tokens
+                .filter(user_id.eq(_user_id))
+                .filter(family_id.eq(_family_id)),
+        )
+        .execute(conn)
+    }
+
     pub fn refresh_tokens(
         conn: &mut PgConnection,
         user_token: UserToken,
+        client: &ClientInfo,
         config: Data<Config>,
-    ) -> UserTokensDTO {
-        let user = User::find_user_by_id(conn, user_token.user_id)
-            .expect("Undefined behavior on the DB side");
+    ) -> Result<UserTokensDTO, RefreshTokenError> {
+        if user_token.expiration_date < Utc::now().naive_utc() {
+            diesel::delete(&user_token).execute(conn).unwrap();
+            return Err(RefreshTokenError::new("Refresh token has expired!"));
+        }
 
-        diesel::delete(user_tokens.filter(refresh_token.eq(user_token.refresh_token)))
+        // Rotating is a conditional update, so two concurrent refreshes can't both succeed
+        let rotated_count = diesel::update(user_tokens.filter(id.eq(user_token.id)))
+            .filter(rotated.eq(false))
+            .set((rotated.eq(true), last_used_date.eq(Utc::now().naive_utc())))
             .execute(conn)
             .unwrap();
 
-        let new_access_token = UserToken::generate_access_token(user.clone(), config);
-        let new_refresh_token = UserToken::generate_refresh_token(conn, user.clone());
+        if rotated_count == 0 {
+            // A rotated-out token can only be presented again if it was copied somewhere,
+            // so the whole session is considered compromised
+            warn!(
+                "Reuse of a rotated refresh token detected for user {}, revoking token family {} (possible token theft)",
+                user_token.user_id, user_token.family_id
+            );
+            Self::revoke_family(conn, &user_token.family_id).unwrap();
+            return Err(RefreshTokenError {
+                revoked_user_id: Some(user_token.user_id),
+                ..RefreshTokenError::new("Refresh token has been revoked!")
+            });
+        }
 
-        let new_user_tokens = UserTokensDTO {
-            access_token: new_access_token,
-            refresh_token: new_refresh_token,
-        };
+        let user = User::find_user_by_id(conn, user_token.user_id)
+            .expect("Undefined behavior on the DB side");
+        if user.disabled {
+            return Err(RefreshTokenError::new("Account is disabled"));
+        }
 
-        new_user_tokens
+        Ok(Self::issue_tokens(
+            conn,
+            user,
+            Some(user_token.family_id),
+            client,
+            config,
+        ))
     }
 }
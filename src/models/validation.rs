@@ -0,0 +1,98 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Limits of the users table columns
+pub const LOGIN_MIN_LENGTH: usize = 3;
+pub const LOGIN_MAX_LENGTH: usize = 16;
+pub const EMAIL_MAX_LENGTH: usize = 32;
+pub const NICKNAME_MAX_LENGTH: usize = 16;
+
+const EMAIL_LOCAL_PART_SPECIAL: &str = "!#$%&'*+/=?^_`{|}~.-";
+
+/// A problem with a single field of a request body
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: String) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// Checks a request body before it reaches the database, reporting every invalid field.
+pub trait Validate {
+    fn validate(&self) -> Result<(), Vec<FieldError>>;
+}
+
+/// Emails are compared and stored in lower case
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn login_error(login: &str) -> Option<String> {
+    let length = login.chars().count();
+
+    if !(LOGIN_MIN_LENGTH..=LOGIN_MAX_LENGTH).contains(&length) {
+        Some(format!(
+            "Login must be between {} and {} characters long",
+            LOGIN_MIN_LENGTH, LOGIN_MAX_LENGTH
+        ))
+    } else if !login
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        Some("Login may only contain latin letters, digits, '_', '.' and '-'".to_string())
+    } else {
+        None
+    }
+}
+
+/// A pragmatic subset of RFC 5322: dot-atom local part and a domain with a TLD
+pub fn email_error(email: &str) -> Option<String> {
+    if email.is_empty() {
+        return Some("Email must not be empty".to_string());
+    }
+    if email.chars().count() > EMAIL_MAX_LENGTH {
+        return Some(format!(
+            "Email must be at most {} characters long",
+            EMAIL_MAX_LENGTH
+        ));
+    }
+
+    let (local_part, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return Some("Email is not valid".to_string()),
+    };
+
+    let local_part_valid = !local_part.is_empty()
+        && local_part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || EMAIL_LOCAL_PART_SPECIAL.contains(c))
+        && !local_part.starts_with('.')
+        && !local_part.ends_with('.')
+        && !local_part.contains("..");
+
+    let labels = domain.split('.').collect::<Vec<&str>>();
+    let domain_valid = labels.len() > 1
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels.last().map_or(false, |tld| {
+            tld.len() > 1 && tld.chars().all(|c| c.is_ascii_alphabetic())
+        });
+
+    if local_part_valid && domain_valid {
+        None
+    } else {
+        Some("Email is not valid".to_string())
+    }
+}
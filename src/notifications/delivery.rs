@@ -0,0 +1,184 @@
+use crate::config::app::Config;
+use crate::models::notification::{DeliveryOutcome, PendingNotification};
+use crate::models::product::{Product, ProductDTO};
+use crate::models::user::User;
+use crate::models::user_notification_settings::{NotificationType, UserNotificationSettings};
+use crate::notifications::{NotificationChannel, NotificationMessage};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+pub const DELIVERY_BATCH_SIZE: i64 = 100;
+// Long enough for a batch to go through a slow mail server
+const DELIVERY_LEASE_SECS: i64 = 300;
+const LAST_ERROR_MAX_LENGTH: usize = 255;
+
+#[derive(Default)]
+pub struct DeliveryReport {
+    pub claimed: usize,
+    pub delivered: usize,
+    pub postponed: usize,
+    pub failed: usize,
+}
+
+/// Delivers a batch of due price drop notifications on the channels each user wants
+/// them on. Failed channels are retried with exponential backoff; channels that
+/// already got the notification are skipped. Users who turned instant price drops off
+/// don't get them right away; for them they stay undelivered, for the digest.
+pub fn deliver_due(
+    conn: &mut PgConnection,
+    channels: &[Arc<dyn NotificationChannel>],
+    config: &Config,
+) -> QueryResult<DeliveryReport> {
+    let mut report = DeliveryReport::default();
+    let notifications =
+        PendingNotification::claim_due(conn, DELIVERY_BATCH_SIZE, DELIVERY_LEASE_SECS)?;
+    report.claimed = notifications.len();
+
+    for notification in notifications {
+        let outcome = deliver(conn, channels, config, &notification)?;
+        if outcome.delivered {
+            report.delivered += 1;
+        } else if outcome.next_attempt_date.is_some() {
+            report.postponed += 1;
+        } else if outcome.last_error.is_some() {
+            report.failed += 1;
+        }
+        PendingNotification::record(conn, notification.id, &outcome)?;
+    }
+
+    Ok(report)
+}
+
+fn deliver(
+    conn: &mut PgConnection,
+    channels: &[Arc<dyn NotificationChannel>],
+    config: &Config,
+    notification: &PendingNotification,
+) -> QueryResult<DeliveryOutcome> {
+    let now = Utc::now();
+    let mut outcome = DeliveryOutcome {
+        delivered: !notification.sent_channels.is_empty(),
+        attempts: notification.attempts,
+        next_attempt_date: None,
+        last_error: None,
+        sent_channels: notification.sent_channels.clone(),
+    };
+
+    let user = User::find_user_by_id(conn, notification.user_id)?;
+    if user.disabled {
+        return Ok(outcome);
+    }
+
+    let settings = UserNotificationSettings::get(conn, notification.user_id)?;
+    // Empty if the user turned instant price drops off
+    let due_channels = channels
+        .iter()
+        .filter(|channel| {
+            !outcome
+                .sent_channels
+                .iter()
+                .any(|sent| sent == channel.kind().as_str())
+                && channel.reaches(&user)
+                && settings.allows(channel.kind(), NotificationType::PriceDrop)
+        })
+        .collect::<Vec<&Arc<dyn NotificationChannel>>>();
+    if due_channels.is_empty() {
+        return Ok(outcome);
+    }
+
+    if let Some(quiet_hours_end) = settings.quiet_hours_end(now) {
+        outcome.next_attempt_date = Some(quiet_hours_end.naive_utc());
+        return Ok(outcome);
+    }
+
+    let product = Product::get_product(conn, notification.product_id, None)?;
+    let message = price_drop_message(&user, &product, notification, config);
+
+    let mut errors = Vec::new();
+    for channel in due_channels {
+        match channel.send(&user, &message) {
+            Ok(()) => outcome
+                .sent_channels
+                .push(channel.kind().as_str().to_string()),
+            Err(e) => errors.push(format!("{}: {}", channel.kind().as_str(), e)),
+        }
+    }
+
+    if errors.is_empty() {
+        outcome.delivered = true;
+        return Ok(outcome);
+    }
+
+    outcome.attempts += 1;
+    outcome.last_error = Some(
+        errors
+            .join("; ")
+            .chars()
+            .take(LAST_ERROR_MAX_LENGTH)
+            .collect(),
+    );
+    if outcome.attempts < config.notification_max_attempts {
+        outcome.next_attempt_date = Some(
+            (now + retry_delay(config.notification_retry_base_secs, outcome.attempts)).naive_utc(),
+        );
+    } else {
+        // Given up; it still counts as delivered if another channel got it
+        outcome.delivered = !outcome.sent_channels.is_empty();
+    }
+
+    Ok(outcome)
+}
+
+// Doubles with each failed attempt, up to a day
+fn retry_delay(base_secs: i64, attempts: i32) -> Duration {
+    let factor = 1i64 << (attempts - 1).clamp(0, 20);
+    Duration::seconds(base_secs.saturating_mul(factor).min(86400))
+}
+
+fn price_drop_message(
+    user: &User,
+    product: &ProductDTO,
+    notification: &PendingNotification,
+    config: &Config,
+) -> NotificationMessage {
+    // The store the drop was seen in, which may not be the cheapest overall when the
+    // subscription is restricted to some stores
+    let cheapest_store = product
+        .prices
+        .iter()
+        .find(|price| Some(price.store_id) == notification.store_id)
+        .or_else(|| {
+            product
+                .prices
+                .iter()
+                .min_by(|a, b| a.price.partial_cmp(&b.price).unwrap_or(Ordering::Equal))
+        })
+        .map(|price| format!("The cheapest store is now {}.\n", price.store_name))
+        .unwrap_or_default();
+
+    let new_price = notification.new_price.unwrap_or_default();
+    let price_change = match notification.old_price {
+        Some(old_price) => format!("dropped from {:.2} to {:.2}", old_price, new_price),
+        // The first price seen already met the target
+        None => format!("is {:.2}, within your target price", new_price),
+    };
+
+    NotificationMessage {
+        subject: format!("Price drop: {}", product.product.name),
+        body: format!(
+            "Hi {},\n\n\
+             The price of {} {}.\n\
+             {}\n\
+             {}/product/{}\n\n\
+             You can choose which notifications you get in your notification settings.",
+            user.login,
+            product.product.name,
+            price_change,
+            cheapest_store,
+            config.frontend_url,
+            product.product.id
+        ),
+    }
+}
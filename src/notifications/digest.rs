@@ -0,0 +1,225 @@
+use crate::config::app::Config;
+use crate::models::notification::DigestEntry;
+use crate::models::user::User;
+use crate::models::user_notification_settings::{NotificationType, UserNotificationSettings};
+use crate::notifications::{NotificationChannel, NotificationMessage};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use log::error;
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct DigestReport {
+    pub sent: usize,
+    pub failed: usize,
+}
+
+enum DigestOutcome {
+    Sent,
+    Failed,
+    NotDue,
+}
+
+// Long enough for a digest to go out on every channel
+const DIGEST_LEASE_SECS: i64 = 300;
+
+/// Sends a digest to every user whose frequency window has elapsed since their last one,
+/// or since their oldest undigested price drop if they never got one. Each channel gets
+/// the price drops it hasn't had, and they are marked as delivered once all of the
+/// user's channels had them; a channel that failed gets them again with the next digest.
+pub fn send_due(
+    conn: &mut PgConnection,
+    channels: &[Arc<dyn NotificationChannel>],
+    config: &Config,
+) -> QueryResult<DigestReport> {
+    let mut report = DigestReport::default();
+
+    for user_id in DigestEntry::user_ids(conn)? {
+        match send_to_user(conn, channels, config, user_id)? {
+            DigestOutcome::Sent => report.sent += 1,
+            DigestOutcome::Failed => report.failed += 1,
+            DigestOutcome::NotDue => {}
+        }
+    }
+
+    Ok(report)
+}
+
+/// A digest an instance took the lease on
+struct ClaimedDigest {
+    user: User,
+    entries: Vec<DigestEntry>,
+    channels: Vec<Arc<dyn NotificationChannel>>,
+}
+
+fn send_to_user(
+    conn: &mut PgConnection,
+    channels: &[Arc<dyn NotificationChannel>],
+    config: &Config,
+    user_id: i32,
+) -> QueryResult<DigestOutcome> {
+    let now = Utc::now();
+    // Nothing stays locked while the digest is sent, which can take a while
+    let Some(digest) = conn.transaction(|conn| claim(conn, channels, user_id, now))? else {
+        return Ok(DigestOutcome::NotDue);
+    };
+
+    let mut attempted = false;
+    let mut sent: Vec<(&str, Vec<i32>)> = Vec::new();
+    for channel in &digest.channels {
+        let kind = channel.kind().as_str();
+        let entries = digest
+            .entries
+            .iter()
+            .filter(|entry| !entry.sent_channels.iter().any(|sent| sent == kind))
+            .collect::<Vec<&DigestEntry>>();
+        if entries.is_empty() {
+            continue;
+        }
+
+        attempted = true;
+        let message = digest_message(&digest.user, &entries, config);
+        match channel.send(&digest.user, &message) {
+            Ok(()) => sent.push((kind, entries.iter().map(|entry| entry.id).collect())),
+            Err(e) => error!(
+                "Failed to send the digest to user {} by {}: {}",
+                user_id, kind, e
+            ),
+        }
+    }
+
+    // Delivered once every channel the user gets digests on had them
+    let delivered_ids = digest
+        .entries
+        .iter()
+        .filter(|entry| {
+            digest.channels.iter().all(|channel| {
+                let kind = channel.kind().as_str();
+                entry
+                    .sent_channels
+                    .iter()
+                    .any(|sent_kind| sent_kind == kind)
+                    || sent
+                        .iter()
+                        .any(|(sent_kind, ids)| *sent_kind == kind && ids.contains(&entry.id))
+            })
+        })
+        .map(|entry| entry.id)
+        .collect::<Vec<i32>>();
+    conn.transaction(|conn| {
+        for (kind, sent_ids) in &sent {
+            DigestEntry::mark_sent(conn, sent_ids, kind)?;
+        }
+        DigestEntry::mark_delivered(conn, &delivered_ids)?;
+        // Retried on the next run if it went out nowhere
+        let sent_date = (!sent.is_empty()).then(|| now.naive_utc());
+        UserNotificationSettings::record_digest(conn, user_id, sent_date)
+    })?;
+
+    Ok(match (attempted, sent.is_empty()) {
+        (false, _) => DigestOutcome::NotDue,
+        (true, true) => DigestOutcome::Failed,
+        (true, false) => DigestOutcome::Sent,
+    })
+}
+
+/// Takes the lease on the user's digest if it is due and no other instance has it
+fn claim(
+    conn: &mut PgConnection,
+    channels: &[Arc<dyn NotificationChannel>],
+    user_id: i32,
+    now: DateTime<Utc>,
+) -> QueryResult<Option<ClaimedDigest>> {
+    let Some(state) = UserNotificationSettings::lock_for_digest(conn, user_id)? else {
+        return Ok(None);
+    };
+    if state
+        .lease_until
+        .map_or(false, |lease_until| now.naive_utc() < lease_until)
+    {
+        return Ok(None);
+    }
+    let entries = DigestEntry::for_user(conn, user_id)?;
+    let Some(oldest_entry) = entries.first() else {
+        return Ok(None);
+    };
+
+    let settings = UserNotificationSettings::get(conn, user_id)?;
+    let window_start = state.last_digest_date.unwrap_or(oldest_entry.created_date);
+    if now.naive_utc() < window_start + Duration::days(settings.frequency_in_days as i64)
+        || settings.quiet_hours_end(now).is_some()
+    {
+        return Ok(None);
+    }
+
+    let user = User::find_user_by_id(conn, user_id)?;
+    if user.disabled {
+        return Ok(None);
+    }
+
+    let due_channels = channels
+        .iter()
+        .filter(|channel| {
+            channel.reaches(&user) && settings.allows(channel.kind(), NotificationType::Digest)
+        })
+        .cloned()
+        .collect::<Vec<Arc<dyn NotificationChannel>>>();
+    if due_channels.is_empty() {
+        return Ok(None);
+    }
+
+    UserNotificationSettings::lease_digest(
+        conn,
+        user_id,
+        now.naive_utc() + Duration::seconds(DIGEST_LEASE_SECS),
+    )?;
+
+    Ok(Some(ClaimedDigest {
+        user,
+        entries,
+        channels: due_channels,
+    }))
+}
+
+fn digest_message(user: &User, entries: &[&DigestEntry], config: &Config) -> NotificationMessage {
+    // One line per product, from the price before its first drop to the latest one
+    let mut lines: Vec<(i32, String, Option<f32>, f32)> = Vec::new();
+    for entry in entries {
+        let (old_price, new_price) = (entry.old_price, entry.new_price.unwrap_or_default());
+        match lines.iter_mut().find(|line| line.0 == entry.product_id) {
+            Some(line) => line.3 = new_price,
+            None => lines.push((
+                entry.product_id,
+                entry.product_name.clone(),
+                old_price,
+                new_price,
+            )),
+        }
+    }
+
+    let summary = lines
+        .iter()
+        .map(|(_, name, old_price, new_price)| match old_price {
+            Some(old_price) => format!("- {}: {:.2} -> {:.2}", name, old_price, new_price),
+            // The first price seen already met the target
+            None => format!("- {}: {:.2}", name, new_price),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    NotificationMessage {
+        subject: format!(
+            "Your price digest: {} product{} got cheaper",
+            lines.len(),
+            if lines.len() == 1 { "" } else { "s" }
+        ),
+        body: format!(
+            "Hi {},\n\n\
+             These subscribed products got cheaper since your last digest:\n\n\
+             {}\n\n\
+             {}/subscriptions\n\n\
+             You can change how often you get this digest in your notification settings.",
+            user.login, summary, config.frontend_url
+        ),
+    }
+}
@@ -0,0 +1,35 @@
+use crate::mailer::{Email, Mailer};
+use crate::models::user::User;
+use crate::models::user_notification_settings::NotificationChannelKind;
+use crate::notifications::{NotificationChannel, NotificationMessage};
+use std::sync::Arc;
+
+/// Sends notifications through the configured mail transport
+pub struct EmailChannel {
+    mailer: Arc<dyn Mailer>,
+}
+
+impl EmailChannel {
+    pub fn new(mailer: Arc<dyn Mailer>) -> EmailChannel {
+        EmailChannel { mailer }
+    }
+}
+
+impl NotificationChannel for EmailChannel {
+    fn kind(&self) -> NotificationChannelKind {
+        NotificationChannelKind::Email
+    }
+
+    // Unverified addresses may belong to someone else
+    fn reaches(&self, user: &User) -> bool {
+        user.email_verified
+    }
+
+    fn send(&self, user: &User, message: &NotificationMessage) -> Result<(), String> {
+        self.mailer.send(&Email {
+            to: user.email.clone(),
+            subject: message.subject.clone(),
+            body: message.body.clone(),
+        })
+    }
+}
@@ -0,0 +1,54 @@
+use crate::config::app::Config;
+use crate::models::user::User;
+use crate::models::user_notification_settings::NotificationChannelKind;
+use crate::notifications::{NotificationChannel, NotificationMessage};
+use log::info;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Stands in for push notifications until there is a push provider: writes them to the
+/// log, and appends them to `NOTIFICATION_LOG_PATH` when it is set.
+pub struct LogChannel {
+    file: Option<Mutex<std::fs::File>>,
+}
+
+impl LogChannel {
+    pub fn new(config: &Config) -> LogChannel {
+        let file = config.notification_log_path.as_ref().map(|path| {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("Failed to open notification log file");
+            Mutex::new(file)
+        });
+
+        LogChannel { file }
+    }
+}
+
+impl NotificationChannel for LogChannel {
+    fn kind(&self) -> NotificationChannelKind {
+        NotificationChannelKind::Push
+    }
+
+    fn send(&self, user: &User, message: &NotificationMessage) -> Result<(), String> {
+        info!(
+            "Notification to user {} with subject '{}'",
+            user.id, message.subject
+        );
+
+        if let Some(ref file) = self.file {
+            let mut file = file.lock().unwrap();
+            writeln!(
+                file,
+                "User: {}\nSubject: {}\n\n{}\n\n---\n",
+                user.id, message.subject, message.body
+            )
+            .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+pub mod delivery;
+pub mod digest;
+pub mod email_channel;
+pub mod log_channel;
+
+use crate::config::app::Config;
+use crate::mailer::Mailer;
+use crate::models::user::User;
+use crate::models::user_notification_settings::NotificationChannelKind;
+use crate::notifications::email_channel::EmailChannel;
+use crate::notifications::log_channel::LogChannel;
+use std::sync::Arc;
+
+pub struct NotificationMessage {
+    pub subject: String,
+    pub body: String,
+}
+
+/// A way of delivering notifications to users. Implementations are blocking.
+pub trait NotificationChannel: Send + Sync {
+    /// The channel in the user's notification settings
+    fn kind(&self) -> NotificationChannelKind;
+
+    /// Whether the user can be reached on this channel at all
+    fn reaches(&self, _user: &User) -> bool {
+        true
+    }
+
+    fn send(&self, user: &User, message: &NotificationMessage) -> Result<(), String>;
+}
+
+pub fn get_channels(config: &Config, mailer: Arc<dyn Mailer>) -> Vec<Arc<dyn NotificationChannel>> {
+    config
+        .notification_channels
+        .iter()
+        .map(|channel| -> Arc<dyn NotificationChannel> {
+            match channel.as_str() {
+                "email" => Arc::new(EmailChannel::new(mailer.clone())),
+                "log" => Arc::new(LogChannel::new(config)),
+                channel => panic!("Unknown notification channel '{}'", channel),
+            }
+        })
+        .collect()
+}
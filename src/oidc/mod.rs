@@ -0,0 +1,374 @@
+use crate::config::app::Config;
+use awc::http::header::ACCEPT;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// Providers rarely change their endpoints, but do rotate signing keys
+const METADATA_TTL: Duration = Duration::from_secs(3600);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An OpenID Connect provider users can log in with
+#[derive(Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Not needed for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+impl fmt::Debug for OidcProviderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcProviderConfig")
+            .field("name", &self.name)
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+pub enum OidcError {
+    /// The provider couldn't be reached or answered with something unexpected
+    Provider(String),
+    /// The provider rejected the code, or the ID token didn't pass validation
+    Rejected(String),
+}
+
+/// The claims of a validated ID token
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    // Some providers send it as a string
+    #[serde(default, deserialize_with = "deserialize_lenient_bool")]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+    azp: Option<String>,
+}
+
+fn deserialize_lenient_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(value) => value,
+        serde_json::Value::String(value) => value == "true",
+        _ => false,
+    })
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    token_endpoint_auth_methods_supported: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+struct OidcProvider {
+    config: OidcProviderConfig,
+    metadata: RwLock<Option<(Instant, Arc<ProviderMetadata>)>>,
+    jwks: RwLock<Option<Arc<JwkSet>>>,
+}
+
+/// Runs the authorization code flow with PKCE against the configured providers.
+/// Endpoints and signing keys are taken from each issuer's discovery document.
+pub struct OidcClient {
+    providers: HashMap<String, OidcProvider>,
+    redirect_url: String,
+}
+
+/// The S256 PKCE challenge for a code verifier
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn http_client() -> awc::Client {
+    awc::Client::builder().timeout(REQUEST_TIMEOUT).finish()
+}
+
+async fn fetch_json<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T, OidcError> {
+    let mut response = http_client()
+        .get(url)
+        .insert_header((ACCEPT, "application/json"))
+        .send()
+        .await
+        .map_err(|e| OidcError::Provider(format!("Failed to fetch {}: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(OidcError::Provider(format!(
+            "Failed to fetch {}: {}",
+            url,
+            response.status()
+        )));
+    }
+
+    response
+        .json::<T>()
+        .await
+        .map_err(|e| OidcError::Provider(format!("Invalid response from {}: {}", url, e)))
+}
+
+impl OidcClient {
+    pub fn new(config: &Config) -> OidcClient {
+        OidcClient {
+            providers: config
+                .oidc_providers
+                .iter()
+                .map(|provider| {
+                    (
+                        provider.name.clone(),
+                        OidcProvider {
+                            config: provider.clone(),
+                            metadata: RwLock::new(None),
+                            jwks: RwLock::new(None),
+                        },
+                    )
+                })
+                .collect(),
+            redirect_url: config.oidc_redirect_url.clone(),
+        }
+    }
+
+    pub fn provider_names(&self) -> Vec<String> {
+        let mut names = self.providers.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    pub fn has_provider(&self, name: &str) -> bool {
+        self.providers.contains_key(name)
+    }
+
+    /// The URL to send the user to. `state` and `nonce` are echoed back by the provider
+    /// and must be checked on the callback.
+    pub async fn authorization_url(
+        &self,
+        provider: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, OidcError> {
+        let provider = self.provider(provider)?;
+        let metadata = self.metadata(provider).await?;
+
+        let query = serde_urlencoded::to_string([
+            ("response_type", "code"),
+            ("client_id", &provider.config.client_id),
+            ("redirect_uri", &self.redirect_url),
+            ("scope", &provider.config.scopes),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", &code_challenge(code_verifier)),
+            ("code_challenge_method", "S256"),
+        ])
+        .map_err(|e| OidcError::Provider(e.to_string()))?;
+
+        let separator = if metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}{}",
+            metadata.authorization_endpoint, separator, query
+        ))
+    }
+
+    /// Redeems the authorization code and returns the claims of the validated ID token
+    pub async fn exchange_code(
+        &self,
+        provider: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let provider = self.provider(provider)?;
+        let metadata = self.metadata(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("code_verifier", code_verifier),
+            ("client_id", &provider.config.client_id),
+        ];
+        let mut request = http_client()
+            .post(&metadata.token_endpoint)
+            .insert_header((ACCEPT, "application/json"));
+        if let Some(ref client_secret) = provider.config.client_secret {
+            // client_secret_basic is the default when the provider doesn't say
+            let basic_supported = metadata
+                .token_endpoint_auth_methods_supported
+                .as_ref()
+                .map_or(true, |methods| {
+                    methods.iter().any(|method| method == "client_secret_basic")
+                });
+            if basic_supported {
+                request = request.basic_auth(&provider.config.client_id, client_secret);
+            } else {
+                form.push(("client_secret", client_secret));
+            }
+        }
+
+        let mut response = request.send_form(&form).await.map_err(|e| {
+            OidcError::Provider(format!("Failed to reach the token endpoint: {}", e))
+        })?;
+        if !response.status().is_success() {
+            return Err(match response.json::<TokenErrorResponse>().await {
+                Ok(error) => OidcError::Rejected(format!(
+                    "The provider rejected the code: {}",
+                    error.error_description.unwrap_or(error.error)
+                )),
+                Err(_) => OidcError::Provider(format!(
+                    "The token endpoint answered with {}",
+                    response.status()
+                )),
+            });
+        }
+        let id_token = response
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| OidcError::Provider(format!("Invalid token response: {}", e)))?
+            .id_token
+            .ok_or_else(|| OidcError::Provider("No ID token in the token response".to_string()))?;
+
+        self.validate_id_token(provider, &metadata, &id_token, nonce)
+            .await
+    }
+
+    async fn validate_id_token(
+        &self,
+        provider: &OidcProvider,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let rejected = |e: jsonwebtoken::errors::Error| {
+            OidcError::Rejected(format!("ID token is invalid: {}", e))
+        };
+        let header = jsonwebtoken::decode_header(id_token).map_err(rejected)?;
+
+        let key = match header.alg {
+            // Symmetric ID tokens are signed with the client secret
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                match provider.config.client_secret {
+                    Some(ref client_secret) => DecodingKey::from_secret(client_secret.as_bytes()),
+                    None => {
+                        return Err(OidcError::Rejected(
+                            "ID token is signed with a client secret, but none is configured"
+                                .to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                let jwk_set = self.jwks(provider, metadata, false).await?;
+                let jwk_set = match header.kid {
+                    Some(ref kid) if jwk_set.find(kid).is_none() => {
+                        // The provider may have rotated its keys since we fetched them
+                        self.jwks(provider, metadata, true).await?
+                    }
+                    _ => jwk_set,
+                };
+                let jwk = match header.kid {
+                    Some(ref kid) => jwk_set.find(kid),
+                    None if jwk_set.keys.len() == 1 => jwk_set.keys.first(),
+                    None => None,
+                }
+                .ok_or_else(|| {
+                    OidcError::Rejected("ID token is signed with an unknown key".to_string())
+                })?;
+                DecodingKey::from_jwk(jwk).map_err(rejected)?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(rejected)?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::Rejected(
+                "ID token was not issued for this login".to_string(),
+            ));
+        }
+        if let Some(ref azp) = claims.azp {
+            if azp != &provider.config.client_id {
+                return Err(OidcError::Rejected(
+                    "ID token was issued to another client".to_string(),
+                ));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    fn provider(&self, name: &str) -> Result<&OidcProvider, OidcError> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| OidcError::Rejected(format!("Unknown provider '{}'", name)))
+    }
+
+    async fn metadata(&self, provider: &OidcProvider) -> Result<Arc<ProviderMetadata>, OidcError> {
+        if let Some((fetched, ref metadata)) = *provider.metadata.read().unwrap() {
+            if fetched.elapsed() < METADATA_TTL {
+                return Ok(metadata.clone());
+            }
+        }
+
+        let issuer = provider.config.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata =
+            fetch_json(&format!("{}/.well-known/openid-configuration", issuer)).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(OidcError::Provider(format!(
+                "The discovery document of '{}' is for another issuer",
+                provider.config.name
+            )));
+        }
+
+        let metadata = Arc::new(metadata);
+        *provider.metadata.write().unwrap() = Some((Instant::now(), metadata.clone()));
+        Ok(metadata)
+    }
+
+    async fn jwks(
+        &self,
+        provider: &OidcProvider,
+        metadata: &ProviderMetadata,
+        refresh: bool,
+    ) -> Result<Arc<JwkSet>, OidcError> {
+        if !refresh {
+            if let Some(ref jwks) = *provider.jwks.read().unwrap() {
+                return Ok(jwks.clone());
+            }
+        }
+
+        let jwks = Arc::new(fetch_json::<JwkSet>(&metadata.jwks_uri).await?);
+        *provider.jwks.write().unwrap() = Some(jwks.clone());
+        Ok(jwks)
+    }
+}
@@ -0,0 +1,176 @@
+use native_tls::{Certificate, TlsConnector, TlsConnectorBuilder};
+use postgres_native_tls::MakeTlsConnector;
+use std::path::PathBuf;
+use std::{env, fs};
+use tokio_postgres::config::SslMode;
+use url::Url;
+
+// libpq's TLS options; tokio-postgres only understands some `sslmode`s and no root
+// certificates, so these are taken out of the URL and handled here
+static SSL_MODE: &str = "sslmode";
+static SSL_ROOT_CERT: &str = "sslrootcert";
+static SSL_MODE_ENV: &str = "PGSSLMODE";
+static SSL_ROOT_CERT_ENV: &str = "PGSSLROOTCERT";
+// Trusts the system's roots rather than a file
+static SSL_ROOT_CERT_SYSTEM: &str = "system";
+static PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+/// How the price updates listener connects, following the `sslmode` and `sslrootcert`
+/// of `DATABASE_URL` (or `PGSSLMODE` and `PGSSLROOTCERT`) like the pool's libpq does
+#[derive(Clone)]
+pub struct ListenerConfig {
+    pub config: tokio_postgres::Config,
+    pub tls: MakeTlsConnector,
+}
+
+impl ListenerConfig {
+    pub fn from_database_url(database_url: &str) -> Result<ListenerConfig, String> {
+        let (database_url, ssl_mode, ssl_root_cert) = take_ssl_options(database_url)?;
+        let ssl_mode = ssl_mode
+            .or_else(|| env::var(SSL_MODE_ENV).ok())
+            .unwrap_or_else(|| "prefer".to_string());
+        let ssl_root_cert = ssl_root_cert.or_else(|| env::var(SSL_ROOT_CERT_ENV).ok());
+
+        let mut config = database_url
+            .parse::<tokio_postgres::Config>()
+            .map_err(|e| e.to_string())?;
+        let mut builder = TlsConnector::builder();
+        match ssl_mode.as_str() {
+            "disable" => {
+                config.ssl_mode(SslMode::Disable);
+            }
+            // Only encrypted, the certificate isn't checked
+            "prefer" => {
+                builder
+                    .danger_accept_invalid_certs(true)
+                    .danger_accept_invalid_hostnames(true);
+                config.ssl_mode(SslMode::Prefer);
+            }
+            // Likewise, unless there is a root certificate, with which libpq checks who
+            // signed the certificate like verify-ca
+            "require" => {
+                match root_certificates(ssl_root_cert.as_deref())? {
+                    Some(roots) => trust(&mut builder, roots),
+                    None => {
+                        builder.danger_accept_invalid_certs(true);
+                    }
+                }
+                builder.danger_accept_invalid_hostnames(true);
+                config.ssl_mode(SslMode::Require);
+            }
+            "verify-ca" | "verify-full" => {
+                let roots = root_certificates(ssl_root_cert.as_deref())?;
+                trust(&mut builder, roots.unwrap_or(Roots::System));
+                // verify-ca checks who signed the certificate, not who it was issued to
+                builder.danger_accept_invalid_hostnames(ssl_mode == "verify-ca");
+                config.ssl_mode(SslMode::Require);
+            }
+            // allow, which tries without TLS first, isn't supported
+            _ => return Err(format!("{} '{}' is not supported", SSL_MODE, ssl_mode)),
+        }
+
+        let tls = builder
+            .build()
+            .map(MakeTlsConnector::new)
+            .map_err(|e| e.to_string())?;
+
+        Ok(ListenerConfig { config, tls })
+    }
+}
+
+/// Splits `sslmode` and `sslrootcert` off a connection URL or key/value string
+fn take_ssl_options(
+    database_url: &str,
+) -> Result<(String, Option<String>, Option<String>), String> {
+    let mut ssl_mode = None;
+    let mut ssl_root_cert = None;
+
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        let mut url = Url::parse(database_url).map_err(|e| e.to_string())?;
+        let mut other_pairs = Vec::new();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                key if key == SSL_MODE => ssl_mode = Some(value.into_owned()),
+                key if key == SSL_ROOT_CERT => ssl_root_cert = Some(value.into_owned()),
+                _ => other_pairs.push((key.into_owned(), value.into_owned())),
+            }
+        }
+        if other_pairs.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(other_pairs);
+        }
+        return Ok((url.to_string(), ssl_mode, ssl_root_cert));
+    }
+
+    let mut other_options = Vec::new();
+    for option in database_url.split_whitespace() {
+        match option.split_once('=') {
+            Some((key, value)) if key == SSL_MODE => ssl_mode = Some(value.to_string()),
+            Some((key, value)) if key == SSL_ROOT_CERT => ssl_root_cert = Some(value.to_string()),
+            _ => other_options.push(option),
+        }
+    }
+    Ok((other_options.join(" "), ssl_mode, ssl_root_cert))
+}
+
+enum Roots {
+    System,
+    Certificates(Vec<Certificate>),
+}
+
+/// The roots `sslrootcert` names, or the certificates in libpq's default file if there
+/// is one
+fn root_certificates(ssl_root_cert: Option<&str>) -> Result<Option<Roots>, String> {
+    let path = match ssl_root_cert {
+        Some(ssl_root_cert) if ssl_root_cert == SSL_ROOT_CERT_SYSTEM => {
+            return Ok(Some(Roots::System))
+        }
+        Some(ssl_root_cert) => PathBuf::from(ssl_root_cert),
+        None => match env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".postgresql").join("root.crt"),
+            Err(_) => return Ok(None),
+        },
+    };
+    if ssl_root_cert.is_none() && !path.exists() {
+        return Ok(None);
+    }
+
+    let pem = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "root certificate file '{}' can't be read: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let certificates = pem
+        .split_inclusive(PEM_CERTIFICATE_END)
+        .filter(|block| block.contains(PEM_CERTIFICATE_END))
+        .map(|block| Certificate::from_pem(block.trim().as_bytes()))
+        .collect::<Result<Vec<Certificate>, native_tls::Error>>()
+        .map_err(|e| {
+            format!(
+                "root certificate file '{}' is invalid: {}",
+                path.display(),
+                e
+            )
+        })?;
+    if certificates.is_empty() {
+        return Err(format!(
+            "root certificate file '{}' has no certificates",
+            path.display()
+        ));
+    }
+
+    Ok(Some(Roots::Certificates(certificates)))
+}
+
+// A root certificate file is trusted instead of the system's roots, as libpq does
+fn trust(builder: &mut TlsConnectorBuilder, roots: Roots) {
+    if let Roots::Certificates(certificates) = roots {
+        builder.disable_built_in_roots(true);
+        for certificate in certificates {
+            builder.add_root_certificate(certificate);
+        }
+    }
+}
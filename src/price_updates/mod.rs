@@ -0,0 +1,113 @@
+use crate::models::product::PriceUpdateDTO;
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_postgres::AsyncMessage;
+
+pub mod connection;
+
+use connection::ListenerConfig;
+
+// Notified by the trigger on product_store_prices
+const PRICE_UPDATES_CHANNEL: &str = "product_store_prices";
+// Prices a slow client may fall behind by before it misses some
+const PRICE_UPDATES_CAPACITY: usize = 1024;
+// Keeps proxies from closing idle streams
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Fans the prices added on any instance out to the clients streaming them from this one
+#[derive(Clone)]
+pub struct PriceUpdates {
+    sender: broadcast::Sender<PriceUpdateDTO>,
+}
+
+impl PriceUpdates {
+    pub fn new() -> PriceUpdates {
+        let (sender, _) = broadcast::channel(PRICE_UPDATES_CAPACITY);
+        PriceUpdates { sender }
+    }
+
+    /// Listens for new prices until the database connection is lost
+    pub async fn listen(
+        &self,
+        listener_config: &ListenerConfig,
+    ) -> Result<(), tokio_postgres::Error> {
+        let (client, mut connection) = listener_config
+            .config
+            .connect(listener_config.tls.clone())
+            .await?;
+
+        // The connection only makes progress, LISTEN included, while it is polled
+        let sender = self.sender.clone();
+        let messages = actix_web::rt::spawn(async move {
+            let mut messages = stream::poll_fn(|cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                if let AsyncMessage::Notification(notification) = message? {
+                    match serde_json::from_str::<PriceUpdateDTO>(notification.payload()) {
+                        // Nobody streaming prices at the moment is fine
+                        Ok(update) => drop(sender.send(update)),
+                        Err(e) => warn!("Ignoring a malformed price update: {}", e),
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        client
+            .batch_execute(&format!("listen {}", PRICE_UPDATES_CHANNEL))
+            .await?;
+        info!("Listening for price updates");
+
+        match messages.await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Price updates listener stopped: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Server-sent events for the new prices of the products, with a comment now and
+    /// then to keep the connection open
+    pub fn events(&self, product_ids: HashSet<i32>) -> impl Stream<Item = Bytes> {
+        let updates = stream::unfold(
+            (self.sender.subscribe(), product_ids),
+            |(mut receiver, product_ids)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(update) if product_ids.contains(&update.product_id) => {
+                            let event = price_event(&update);
+                            return Some((event, (receiver, product_ids)));
+                        }
+                        Ok(_) => {}
+                        // A slow client misses some prices rather than holding up the others
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("A price stream client missed {} prices", skipped)
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        );
+        let keep_alive = stream::unfold(
+            actix_web::rt::time::interval(KEEP_ALIVE_INTERVAL),
+            |mut interval| async move {
+                interval.tick().await;
+                Some((Bytes::from_static(b": keep-alive\n\n"), interval))
+            },
+        );
+
+        stream::select(updates, keep_alive)
+    }
+}
+
+fn price_event(update: &PriceUpdateDTO) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: price\ndata: {}\n\n",
+        update.id,
+        serde_json::to_string(update).unwrap_or_default()
+    ))
+}
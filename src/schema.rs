@@ -1,5 +1,18 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        key_prefix -> Varchar,
+        key_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_by -> Int4,
+        created_date -> Timestamp,
+        last_used_date -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     categories (id) {
         id -> Int4,
@@ -37,6 +50,26 @@ diesel::table! {
         type_ -> Varchar,
         delivered -> Bool,
         created_date -> Timestamp,
+        old_price -> Nullable<Float4>,
+        new_price -> Nullable<Float4>,
+        attempts -> Int4,
+        next_attempt_date -> Nullable<Timestamp>,
+        last_error -> Nullable<Varchar>,
+        sent_channels -> Array<Varchar>,
+        store_id -> Nullable<Int4>,
+        read_date -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    oidc_auth_requests (id) {
+        id -> Int4,
+        state_hash -> Varchar,
+        provider -> Varchar,
+        code_verifier -> Varchar,
+        nonce -> Varchar,
+        expiration_date -> Timestamp,
+        created_date -> Timestamp,
     }
 }
 
@@ -114,9 +147,30 @@ diesel::table! {
 diesel::table! {
     user_access (id) {
         id -> Int4,
-        user_id -> Int4,
+        user_id -> Nullable<Int4>,
         access_date -> Timestamp,
         is_successful_login -> Bool,
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    user_identities (id) {
+        id -> Int4,
+        user_id -> Int4,
+        provider -> Varchar,
+        subject -> Varchar,
+        email -> Nullable<Varchar>,
+        created_date -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_notification_opt_outs (user_id, channel, notification_type) {
+        user_id -> Int4,
+        channel -> Varchar,
+        notification_type -> Varchar,
     }
 }
 
@@ -126,6 +180,24 @@ diesel::table! {
         email_enabled -> Bool,
         push_enabled -> Bool,
         frequency_in_days -> Int4,
+        timezone -> Varchar,
+        quiet_hours_start -> Nullable<Time>,
+        quiet_hours_end -> Nullable<Time>,
+        last_digest_date -> Nullable<Timestamp>,
+        instant_price_drops -> Bool,
+        digest_lease_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_one_time_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        purpose -> Varchar,
+        token_hash -> Varchar,
+        expiration_date -> Timestamp,
+        used_date -> Nullable<Timestamp>,
+        created_date -> Timestamp,
     }
 }
 
@@ -152,7 +224,16 @@ diesel::table! {
 }
 
 diesel::table! {
-    user_roles (user_id) {
+    user_recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Varchar,
+        used_date -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_roles (user_id, role_id) {
         user_id -> Int4,
         role_id -> Int4,
     }
@@ -184,6 +265,10 @@ diesel::table! {
         previous_minimal_price -> Nullable<Float4>,
         subscribed -> Bool,
         created_date -> Timestamp,
+        target_price -> Nullable<Float4>,
+        min_drop_percent -> Nullable<Float4>,
+        store_ids -> Array<Int4>,
+        retail_chain_ids -> Array<Int4>,
     }
 }
 
@@ -191,7 +276,14 @@ diesel::table! {
     user_tokens (id) {
         id -> Int4,
         user_id -> Int4,
-        refresh_token -> Varchar,
+        token_hash -> Varchar,
+        family_id -> Varchar,
+        expiration_date -> Timestamp,
+        rotated -> Bool,
+        created_date -> Timestamp,
+        last_used_date -> Nullable<Timestamp>,
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
     }
 }
 
@@ -204,10 +296,18 @@ diesel::table! {
         password -> Varchar,
         created_date -> Timestamp,
         updated_date -> Timestamp,
+        token_version -> Int4,
+        email_verified -> Bool,
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled -> Bool,
+        totp_last_used_step -> Nullable<Int8>,
+        disabled -> Bool,
     }
 }
 
+diesel::joinable!(api_keys -> users (created_by));
 diesel::joinable!(cities -> regions (region_id));
+diesel::joinable!(delivered_notifications -> stores (store_id));
 diesel::joinable!(delivered_notifications -> user_subscribed_products (subscribe_id));
 diesel::joinable!(product_store_prices -> product_stores (product_store_id));
 diesel::joinable!(product_stores -> products (product_id));
@@ -220,12 +320,16 @@ diesel::joinable!(stores -> countries (country_id));
 diesel::joinable!(stores -> regions (region_id));
 diesel::joinable!(stores -> retail_chains (retail_chain_id));
 diesel::joinable!(user_access -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(user_notification_opt_outs -> users (user_id));
 diesel::joinable!(user_notification_settings -> users (user_id));
+diesel::joinable!(user_one_time_tokens -> users (user_id));
 diesel::joinable!(user_product_history -> products (product_id));
 diesel::joinable!(user_product_history -> users (user_id));
 diesel::joinable!(user_product_review -> products (product_id));
 diesel::joinable!(user_product_review -> stores (store_id));
 diesel::joinable!(user_product_review -> users (user_id));
+diesel::joinable!(user_recovery_codes -> users (user_id));
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(user_settings -> cities (main_city));
@@ -238,11 +342,13 @@ diesel::joinable!(user_subscribed_products -> users (user_id));
 diesel::joinable!(user_tokens -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
     categories,
     cities,
     companies,
     countries,
     delivered_notifications,
+    oidc_auth_requests,
     product_store_prices,
     product_stores,
     products,
@@ -251,9 +357,13 @@ diesel::allow_tables_to_appear_in_same_query!(
     roles,
     stores,
     user_access,
+    user_identities,
+    user_notification_opt_outs,
     user_notification_settings,
+    user_one_time_tokens,
     user_product_history,
     user_product_review,
+    user_recovery_codes,
     user_roles,
     user_settings,
     user_shopping_carts,
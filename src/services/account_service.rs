@@ -1,84 +1,628 @@
 use crate::config::app::Config;
 use crate::errors::ServiceError;
-use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::mailer::{self, Email, Mailer};
+use crate::middlewares::jwt_middleware::{AuthUser, UserCache};
+use crate::models::two_factor::{
+    DisableTwoFactorDTO, RecoveryCodesDTO, TwoFactor, TwoFactorCodeDTO, TwoFactorEnrollmentDTO,
+    TwoFactorLoginDTO,
+};
 use crate::models::user::{
-    LoginDTO, PasswordRequirements, User, UserDTO, UserSubscribedProductDTO,
+    ChangePasswordDTO, DeleteAccountDTO, LoginDTO, LoginResultDTO, PasswordRequirements,
+    PasswordResetConfirmDTO, PasswordResetRequestDTO, UpdateProfileDTO, User, UserDTO,
+    UserProfileDTO, UserSubscribedProductDTO,
 };
-use crate::models::user_tokens::{UserRefreshTokenDTO, UserTokensDTO};
+use crate::models::user_access::{ClientInfo, UserAccess, UserAccessDTO};
+use crate::models::user_data::UserDataExport;
+use crate::models::user_tokens::{SessionDTO, UserRefreshTokenDTO, UserToken, UserTokensDTO};
+use crate::models::validation::Validate;
 use actix_web::http::StatusCode;
 use actix_web::web::Data;
 use deadpool_diesel::postgres::Pool;
+use diesel::PgConnection;
+
+fn verification_email(to: String, token: &str, config: &Config) -> Email {
+    Email {
+        to,
+        subject: "Confirm your email".to_string(),
+        body: format!(
+            "Welcome to Price Tracker!\n\n\
+             Please confirm your email address by following this link:\n\
+             {}/verify-email?token={}",
+            config.frontend_url, token
+        ),
+    }
+}
+
+pub async fn signup(
+    user: UserDTO,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+    mailer: Data<dyn Mailer>,
+) -> Result<String, ServiceError> {
+    let user = user.normalized();
+    user.validate().map_err(ServiceError::validation)?;
 
-pub async fn signup(user: UserDTO, pool: &Data<Pool>) -> Result<String, ServiceError> {
     let conn = &pool.get().await.unwrap();
+    let signup_config = config.clone();
 
-    conn.interact(|conn| match User::signup(conn, user) {
-        Ok(message) => Ok(message),
-        Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+    let (email, token) = conn
+        .interact(move |conn| match User::signup(conn, user, &signup_config) {
+            Ok(Ok(user)) => match User::issue_email_verification(conn, user.id, &signup_config) {
+                Ok(token) => Ok((user.email, token)),
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            },
+            Ok(Err(field_errors)) => Err(ServiceError::validation(field_errors)),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        })
+        .await
+        .unwrap()?;
+
+    mailer::send_in_background(mailer, verification_email(email, &token, &config));
+
+    Ok("Signup successfully".to_string())
+}
+
+pub async fn verify_email(
+    token: String,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<String, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = conn
+        .interact(move |conn| match User::verify_email(conn, &token) {
+            Ok(user_id) => Ok(user_id),
+            Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+        })
+        .await
+        .unwrap()?;
+
+    user_cache.invalidate(user_id);
+
+    Ok("Email has been verified".to_string())
+}
+
+pub async fn resend_verification_email(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+    mailer: Data<dyn Mailer>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let resend_config = config.clone();
+
+    let (email, token) = conn
+        .interact(move |conn| {
+            if auth_user.email_verified {
+                return Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Email is already verified".to_string(),
+                ));
+            }
+
+            match User::issue_email_verification(conn, auth_user.id, &resend_config) {
+                Ok(token) => Ok((auth_user.user.email, token)),
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            }
+        })
+        .await
+        .unwrap()?;
+
+    mailer::send_in_background(mailer, verification_email(email, &token, &config));
+
+    Ok(())
+}
+
+// Rejects the attempt while the account or the client's address is locked out
+fn check_lockout(
+    conn: &mut PgConnection,
+    user_id: Option<i32>,
+    client: &ClientInfo,
+    config: &Config,
+) -> Result<(), ServiceError> {
+    match UserAccess::get_lockout_secs(conn, user_id, client, config) {
+        Ok(None) => Ok(()),
+        Ok(Some(lockout_secs)) => Err(ServiceError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "Too many failed login attempts, try again in {} seconds",
+                lockout_secs
+            ),
+        )),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
+    }
+}
+
+pub async fn login(
+    login: LoginDTO,
+    client: ClientInfo,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+) -> Result<LoginResultDTO, ServiceError> {
+    let login = login.normalized();
+    login.validate().map_err(ServiceError::validation)?;
+
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        let user_id = User::find_user_by_login_or_email(conn, &login.login_or_email)
+            .ok()
+            .map(|user| user.id);
+
+        check_lockout(conn, user_id, &client, &config)?;
+
+        match User::login(conn, login, &client, config) {
+            Ok(login_result) => Ok(login_result),
+            Err(message) => Err(ServiceError::new(StatusCode::UNAUTHORIZED, message)),
+        }
     })
     .await
     .unwrap()
 }
 
-pub async fn login(
-    login: LoginDTO,
+pub async fn login_two_factor(
+    two_factor_login: TwoFactorLoginDTO,
+    client: ClientInfo,
     pool: &Data<Pool>,
     config: Data<Config>,
 ) -> Result<UserTokensDTO, ServiceError> {
+    let user_id = TwoFactor::decode_challenge(&two_factor_login.challenge_token, &config)
+        .ok_or_else(|| {
+            ServiceError::new(
+                StatusCode::UNAUTHORIZED,
+                "Two-factor challenge is invalid or has expired".to_string(),
+            )
+        })?;
+
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(|conn| match User::login(conn, login, config) {
-        Ok(user_tokens) => Ok(user_tokens),
-        Err(message) => Err(ServiceError::new(StatusCode::UNAUTHORIZED, message)),
+    conn.interact(move |conn| {
+        // Wrong codes count as failed logins, so they are throttled the same way
+        check_lockout(conn, Some(user_id), &client, &config)?;
+
+        match User::login_two_factor(conn, user_id, &two_factor_login.code, &client, config) {
+            Ok(user_tokens) => Ok(user_tokens),
+            Err(message) => Err(ServiceError::new(StatusCode::UNAUTHORIZED, message)),
+        }
     })
     .await
     .unwrap()
 }
 
+pub async fn enroll_two_factor(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+    config: Data<Config>,
+) -> Result<TwoFactorEnrollmentDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(
+            move |conn| match TwoFactor::enroll(conn, &auth_user, &config) {
+                Ok(enrollment) => Ok(enrollment),
+                Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+            },
+        )
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn confirm_two_factor(
+    auth_user: AuthUser,
+    two_factor_code: TwoFactorCodeDTO,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<RecoveryCodesDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(
+            move |conn| match TwoFactor::confirm(conn, &auth_user, &two_factor_code.code) {
+                Ok(recovery_codes) => Ok(recovery_codes),
+                Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+            },
+        )
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn disable_two_factor(
+    auth_user: AuthUser,
+    disable: DisableTwoFactorDTO,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(move |conn| {
+            let user = auth_user.user;
+
+            if !user.totp_enabled {
+                return Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Two-factor authentication is not enabled".to_string(),
+                ));
+            }
+
+            // Check the password first, so a wrong one doesn't use up the code
+            if !user.password_matches(&disable.password)
+                || !TwoFactor::verify(conn, &user, &disable.code).unwrap_or(false)
+            {
+                return Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Password or two-factor code is wrong!".to_string(),
+                ));
+            }
+
+            match TwoFactor::disable(conn, user.id) {
+                Ok(_) => Ok(()),
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            }
+        })
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
 pub async fn refresh_token(
     user_refresh_token: UserRefreshTokenDTO,
+    client: ClientInfo,
     pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
     config: Data<Config>,
 ) -> Result<UserTokensDTO, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
+    conn.interact(move |conn| User::refresh_token(conn, user_refresh_token, &client, config))
+        .await
+        .unwrap()
+        .map_err(|err| {
+            // The revoked session's access tokens must stop working right away
+            if let Some(user_id) = err.revoked_user_id {
+                user_cache.invalidate(user_id);
+            }
+            ServiceError::new(StatusCode::NOT_FOUND, err.message)
+        })
+}
+
+pub async fn logout(
+    user_refresh_token: UserRefreshTokenDTO,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<String, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    match conn
+        .interact(|conn| User::logout(conn, user_refresh_token))
+        .await
+        .unwrap()
+    {
+        Ok(user_id) => {
+            user_cache.invalidate(user_id);
+            Ok("Logout successfully".to_string())
+        }
+        Err(message) => Err(ServiceError::new(StatusCode::NOT_FOUND, message)),
+    }
+}
+
+pub async fn logout_all(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(move |conn| match User::logout_all(conn, auth_user.id) {
+            Ok(_) => Ok(()),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        })
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn get_sessions(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<Vec<SessionDTO>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        match UserToken::get_sessions(conn, auth_user.id, &auth_user.claims.sid) {
+            Ok(sessions) => Ok(sessions),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn revoke_session(
+    auth_user: AuthUser,
+    session_id: String,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(
+            move |conn| match UserToken::revoke_session(conn, auth_user.id, &session_id) {
+                Ok(0) => Err(ServiceError::new(
+                    StatusCode::NOT_FOUND,
+                    "Session not found".to_string(),
+                )),
+                Ok(_) => Ok(()),
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            },
+        )
+        .await
+        .unwrap();
+
+    // The session's access token is rejected once its id is gone from the cache
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn get_access_log(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<Vec<UserAccessDTO>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
     conn.interact(
-        |conn| match User::refresh_token(conn, user_refresh_token, config) {
-            Ok(user_tokens) => Ok(user_tokens),
-            Err(message) => Err(ServiceError::new(StatusCode::NOT_FOUND, message)),
+        move |conn| match UserAccess::get_access_log(conn, auth_user.id) {
+            Ok(access_log) => Ok(access_log),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
         },
     )
     .await
     .unwrap()
 }
 
-pub fn get_password_requirements() -> PasswordRequirements {
-    User::get_password_requirements()
+pub async fn request_password_reset(
+    reset_request: PasswordResetRequestDTO,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+    mailer: Data<dyn Mailer>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let reset_config = config.clone();
+
+    let reset = conn
+        .interact(move |conn| User::request_password_reset(conn, reset_request, &reset_config))
+        .await
+        .unwrap();
+
+    // The response is the same whether the email is registered or not
+    match reset {
+        Ok(Some((email, token))) => {
+            let email = Email {
+                to: email,
+                subject: "Password reset".to_string(),
+                body: format!(
+                    "Someone requested a password reset for your Price Tracker account.\n\n\
+                     Follow this link to choose a new password:\n{}/password-reset?token={}\n\n\
+                     If it wasn't you, just ignore this email.",
+                    config.frontend_url, token
+                ),
+            };
+            mailer::send_in_background(mailer, email);
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
+    }
 }
 
-pub async fn get_subscriptions(
-    token_claims: TokenClaims,
+pub async fn confirm_password_reset(
+    reset_confirm: PasswordResetConfirmDTO,
     pool: &Data<Pool>,
-) -> Result<Vec<UserSubscribedProductDTO>, ServiceError> {
+    user_cache: &Data<UserCache>,
+    config: Data<Config>,
+) -> Result<String, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
+    let user_id = conn
+        .interact(
+            move |conn| match User::confirm_password_reset(conn, reset_confirm, &config) {
+                Ok(user_id) => Ok(user_id),
+                Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+            },
+        )
+        .await
+        .unwrap()?;
+
+    user_cache.invalidate(user_id);
+
+    Ok("Password has been reset".to_string())
+}
 
-        match user {
-            Ok(user) => match User::get_subscriptions(conn, user.id) {
-                Ok(subscriptions) => Ok(subscriptions),
+pub fn get_profile(auth_user: AuthUser) -> UserProfileDTO {
+    UserProfileDTO::from(auth_user.user)
+}
+
+pub async fn update_profile(
+    auth_user: AuthUser,
+    profile: UpdateProfileDTO,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+    config: Data<Config>,
+    mailer: Data<dyn Mailer>,
+) -> Result<UserProfileDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let update_config = config.clone();
+    let user_id = auth_user.id;
+
+    let (profile, token) = conn
+        .interact(move |conn| {
+            match User::update_profile(conn, auth_user.id, profile, &update_config) {
+                Ok(Ok(updated)) => Ok(updated),
+                Ok(Err(field_errors)) => Err(ServiceError::validation(field_errors)),
                 Err(message) => Err(ServiceError::new(
                     StatusCode::BAD_REQUEST,
                     message.to_string(),
                 )),
+            }
+        })
+        .await
+        .unwrap()?;
+
+    user_cache.invalidate(user_id);
+
+    if let Some(token) = token {
+        mailer::send_in_background(
+            mailer,
+            verification_email(profile.email.clone(), &token, &config),
+        );
+    }
+
+    Ok(profile)
+}
+
+pub async fn change_password(
+    auth_user: AuthUser,
+    change: ChangePasswordDTO,
+    client: ClientInfo,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+    config: Data<Config>,
+) -> Result<UserTokensDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(move |conn| {
+            check_lockout(conn, Some(auth_user.id), &client, &config)?;
+
+            match User::change_password(
+                conn,
+                auth_user.id,
+                auth_user.claims.sid,
+                change,
+                &client,
+                config,
+            ) {
+                Ok(user_tokens) => Ok(user_tokens),
+                Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+            }
+        })
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn export_data(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<UserDataExport, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match UserDataExport::collect(conn, auth_user.id) {
+            Ok(export) => Ok(export),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub async fn delete_account(
+    auth_user: AuthUser,
+    deletion: DeleteAccountDTO,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<String, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    let user_id = auth_user.id;
+    let result = conn
+        .interact(
+            move |conn| match User::delete_account(conn, auth_user.id, deletion) {
+                Ok(message) => Ok(message),
+                Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
             },
+        )
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub fn get_password_requirements(config: Data<Config>) -> PasswordRequirements {
+    config.password_policy.requirements()
+}
+
+pub async fn get_subscriptions(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<Vec<UserSubscribedProductDTO>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match User::get_subscriptions(conn, auth_user.id) {
+            Ok(subscriptions) => Ok(subscriptions),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
             )),
-        }
-    })
+        },
+    )
     .await
     .unwrap()
 }
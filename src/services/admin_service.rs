@@ -0,0 +1,176 @@
+use crate::errors::ServiceError;
+use crate::middlewares::jwt_middleware::{AuthUser, UserCache};
+use crate::models::api_key::{ApiKey, ApiKeyDTO, CreateApiKeyDTO, CreatedApiKeyDTO};
+use crate::models::role::Role;
+use crate::models::user::User;
+use actix_web::http::StatusCode;
+use actix_web::web;
+use actix_web::web::Data;
+use deadpool_diesel::postgres::Pool;
+
+pub async fn roles(pool: &Data<Pool>) -> Result<Vec<Role>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(|conn| match Role::get_roles(conn) {
+        Ok(roles) => Ok(roles),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn assign_role(
+    path: web::Path<(i32, String)>,
+    pool: &Data<Pool>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let (user_id, role_name) = path.into_inner();
+
+    conn.interact(move |conn| {
+        let user = User::find_user_by_id(conn, user_id);
+
+        match user {
+            Ok(user) => match Role::assign_role(conn, user.id, &role_name) {
+                Ok(code) => {
+                    if code == 0 {
+                        return Err(ServiceError::new(
+                            StatusCode::BAD_REQUEST,
+                            "Role is already assigned".to_string(),
+                        ));
+                    }
+                    Ok(())
+                }
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            },
+            Err(message) => Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                message.to_string(),
+            )),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn revoke_role(
+    path: web::Path<(i32, String)>,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let (user_id, role_name) = path.into_inner();
+
+    let result = conn
+        .interact(
+            move |conn| match Role::revoke_role(conn, user_id, &role_name) {
+                Ok(code) => {
+                    if code == 0 {
+                        return Err(ServiceError::new(
+                            StatusCode::BAD_REQUEST,
+                            "Role is not assigned".to_string(),
+                        ));
+                    }
+                    Ok(())
+                }
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            },
+        )
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn set_user_disabled(
+    user_id: web::Path<i32>,
+    disabled: bool,
+    pool: &Data<Pool>,
+    user_cache: &Data<UserCache>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+    let user_id = user_id.into_inner();
+
+    let result = conn
+        .interact(
+            move |conn| match User::set_disabled(conn, user_id, disabled) {
+                Ok(0) => Err(ServiceError::new(
+                    StatusCode::NOT_FOUND,
+                    "User not found".to_string(),
+                )),
+                Ok(_) => Ok(()),
+                Err(message) => Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    message.to_string(),
+                )),
+            },
+        )
+        .await
+        .unwrap();
+
+    user_cache.invalidate(user_id);
+    result
+}
+
+pub async fn api_keys(pool: &Data<Pool>) -> Result<Vec<ApiKeyDTO>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(|conn| match ApiKey::get_api_keys(conn) {
+        Ok(api_keys) => Ok(api_keys),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn create_api_key(
+    auth_user: AuthUser,
+    api_key: CreateApiKeyDTO,
+    pool: &Data<Pool>,
+) -> Result<CreatedApiKeyDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match ApiKey::create(conn, auth_user.id, api_key) {
+            Ok(created) => Ok(created),
+            Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub async fn revoke_api_key(
+    api_key_id: web::Path<i32>,
+    pool: &Data<Pool>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match ApiKey::revoke(conn, api_key_id.into_inner()) {
+            Ok(0) => Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                "API key not found".to_string(),
+            )),
+            Ok(_) => Ok(()),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
@@ -3,87 +3,61 @@ use actix_web::web;
 use deadpool_diesel::postgres::Pool;
 
 use crate::errors::ServiceError;
-use crate::middlewares::jwt_middleware::TokenClaims;
+use crate::middlewares::jwt_middleware::AuthUser;
 use crate::models::user::{User, UserShoppingCartDTO};
 
 pub async fn add_to_cart(
     cart_dto: web::Json<UserShoppingCartDTO>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: &web::Data<Pool>,
 ) -> Result<String, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => match User::add_to_cart(conn, user.id, cart_dto.0) {
-                Ok(_) => Ok("".to_string()),
-                Err(message) => Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    message.to_string(),
-                )),
-            },
+    conn.interact(
+        move |conn| match User::add_to_cart(conn, auth_user.id, cart_dto.0) {
+            Ok(_) => Ok("".to_string()),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
             )),
-        }
-    })
+        },
+    )
     .await
     .unwrap()
 }
 
 pub async fn get_cart(
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: &web::Data<Pool>,
 ) -> Result<Vec<UserShoppingCartDTO>, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => match User::get_cart(conn, user.id) {
-                Ok(user_cart) => Ok(user_cart),
-                Err(message) => Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    message.to_string(),
-                )),
-            },
-            Err(message) => Err(ServiceError::new(
-                StatusCode::BAD_REQUEST,
-                message.to_string(),
-            )),
-        }
+    conn.interact(move |conn| match User::get_cart(conn, auth_user.id) {
+        Ok(user_cart) => Ok(user_cart),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
     })
     .await
     .unwrap()
 }
 
 pub async fn get_cart_total_price(
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: &web::Data<Pool>,
 ) -> Result<f32, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => match User::get_cart_total_price(conn, user.id) {
-                Ok(total_price) => Ok(total_price),
-                Err(message) => Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    message.to_string(),
-                )),
-            },
+    conn.interact(
+        move |conn| match User::get_cart_total_price(conn, auth_user.id) {
+            Ok(total_price) => Ok(total_price),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
             )),
-        }
-    })
+        },
+    )
     .await
     .unwrap()
 }
@@ -1,5 +1,10 @@
 pub mod account_service;
+pub mod admin_service;
 pub mod cart_service;
 pub mod category_service;
 pub mod history_service;
+pub mod notification_service;
+pub mod oidc_service;
 pub mod product_service;
+pub mod settings_service;
+pub mod stream_service;
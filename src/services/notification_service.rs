@@ -0,0 +1,71 @@
+use crate::errors::ServiceError;
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::notification::{InboxDTO, InboxFilter, InboxNotificationDTO};
+use crate::models::validation::Validate;
+use actix_web::http::StatusCode;
+use actix_web::web;
+use deadpool_diesel::postgres::Pool;
+
+pub async fn get_inbox(
+    filter: InboxFilter,
+    auth_user: AuthUser,
+    pool: &web::Data<Pool>,
+) -> Result<InboxDTO, ServiceError> {
+    filter.validate().map_err(ServiceError::validation)?;
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match InboxNotificationDTO::get_inbox(conn, auth_user.id, &filter) {
+            Ok(inbox) => Ok(inbox),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub async fn mark_read(
+    notification_id: web::Path<i32>,
+    auth_user: AuthUser,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        match InboxNotificationDTO::mark_read(conn, auth_user.id, notification_id.into_inner()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                "Notification not found".to_string(),
+            )),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn mark_all_read(
+    auth_user: AuthUser,
+    pool: &web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match InboxNotificationDTO::mark_all_read(conn, auth_user.id) {
+            Ok(_) => Ok(()),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
@@ -0,0 +1,108 @@
+use crate::config::app::Config;
+use crate::errors::ServiceError;
+use crate::models::oidc_auth_request::OidcAuthRequest;
+use crate::models::user::{LoginResultDTO, User};
+use crate::models::user_access::ClientInfo;
+use crate::models::user_identity::{OidcAuthorizationDTO, OidcCallbackDTO};
+use crate::oidc::{OidcClient, OidcError};
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use deadpool_diesel::postgres::Pool;
+
+fn oidc_error(error: OidcError) -> ServiceError {
+    match error {
+        OidcError::Provider(message) => ServiceError::new(StatusCode::BAD_GATEWAY, message),
+        OidcError::Rejected(message) => ServiceError::new(StatusCode::UNAUTHORIZED, message),
+    }
+}
+
+pub fn get_providers(oidc_client: Data<OidcClient>) -> Vec<String> {
+    oidc_client.provider_names()
+}
+
+pub async fn authorize(
+    provider: String,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+    oidc_client: Data<OidcClient>,
+) -> Result<(OidcAuthorizationDTO, String), ServiceError> {
+    if !oidc_client.has_provider(&provider) {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown provider '{}'", provider),
+        ));
+    }
+
+    let conn = &pool.get().await.unwrap();
+    let request_provider = provider.clone();
+
+    let pending = conn
+        .interact(move |conn| {
+            OidcAuthRequest::create(
+                conn,
+                &request_provider,
+                config.oidc_auth_request_expires_in_secs,
+            )
+        })
+        .await
+        .unwrap()
+        .map_err(|e| ServiceError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let authorization_url = oidc_client
+        .authorization_url(
+            &provider,
+            &pending.state,
+            &pending.nonce,
+            &pending.code_verifier,
+        )
+        .await
+        .map_err(oidc_error)?;
+
+    Ok((OidcAuthorizationDTO { authorization_url }, pending.state))
+}
+
+pub async fn callback(
+    callback: OidcCallbackDTO,
+    client: ClientInfo,
+    pool: &Data<Pool>,
+    config: Data<Config>,
+    oidc_client: Data<OidcClient>,
+) -> Result<LoginResultDTO, ServiceError> {
+    // The state can only be used once, so a leaked callback URL can't be replayed
+    let auth_request = pool
+        .get()
+        .await
+        .unwrap()
+        .interact(move |conn| OidcAuthRequest::consume(conn, &callback.state))
+        .await
+        .unwrap()
+        .map_err(|e| ServiceError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+        .ok_or_else(|| {
+            ServiceError::new(
+                StatusCode::UNAUTHORIZED,
+                "Login request is invalid or has expired".to_string(),
+            )
+        })?;
+
+    // Don't hold a connection while waiting for the provider
+    let claims = oidc_client
+        .exchange_code(
+            &auth_request.provider,
+            &callback.code,
+            &auth_request.code_verifier,
+            &auth_request.nonce,
+        )
+        .await
+        .map_err(oidc_error)?;
+
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        match User::login_oidc(conn, &auth_request.provider, claims, &client, config) {
+            Ok(login_result) => Ok(login_result),
+            Err(message) => Err(ServiceError::new(StatusCode::UNAUTHORIZED, message)),
+        }
+    })
+    .await
+    .unwrap()
+}
@@ -1,7 +1,11 @@
 use crate::errors::ServiceError;
-use crate::middlewares::jwt_middleware::TokenClaims;
-use crate::models::product::{Product, ProductDTO, ProductFilter, ProductStoreDTO};
-use crate::models::user::{User, UserSubscribedProductDTO};
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::product::{
+    NewPriceDTO, Product, ProductDTO, ProductFilter, ProductQuery, ProductStoreDTO,
+};
+use crate::models::user::{SubscriptionConditionsDTO, UserSubscribedProductDTO};
+use crate::models::user_settings::UserSettings;
+use crate::models::validation::Validate;
 use actix_web::http::StatusCode;
 use actix_web::web;
 use actix_web::web::Data;
@@ -9,38 +13,47 @@ use deadpool_diesel::postgres::Pool;
 
 pub async fn products(
     filters: web::Query<ProductFilter>,
+    auth_user: Option<AuthUser>,
     pool: &Data<Pool>,
 ) -> Result<Vec<ProductDTO>, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(
-        |conn| match Product::get_products_by_filter(conn, filters.0) {
+    conn.interact(move |conn| {
+        let scope = UserSettings::store_scope(conn, auth_user.map(|user| user.id), filters.scope)
+            .map_err(|message| ServiceError::new(StatusCode::BAD_REQUEST, message))?;
+
+        match Product::get_products_by_filter(conn, filters.0, scope) {
             Ok(products) => Ok(products),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
             )),
-        },
-    )
+        }
+    })
     .await
     .unwrap()
 }
 
 pub async fn product(
     product_id: web::Path<i32>,
+    query: web::Query<ProductQuery>,
+    auth_user: Option<AuthUser>,
     pool: &Data<Pool>,
 ) -> Result<ProductDTO, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(
-        |conn| match Product::get_product(conn, product_id.into_inner()) {
+    conn.interact(move |conn| {
+        let scope = UserSettings::store_scope(conn, auth_user.map(|user| user.id), query.scope)
+            .map_err(|message| ServiceError::new(StatusCode::BAD_REQUEST, message))?;
+
+        match Product::get_product(conn, product_id.into_inner(), scope) {
             Ok(product) => Ok(product),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
             )),
-        },
-    )
+        }
+    })
     .await
     .unwrap()
 }
@@ -66,57 +79,61 @@ pub async fn product_by_product_store_id(
 
 pub async fn get_product_subscription(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: &Data<Pool>,
 ) -> Result<UserSubscribedProductDTO, ServiceError> {
     let conn = &pool.get().await.unwrap();
 
-    conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => Ok(Product::get_product_subscription(
-                conn,
-                user.id,
-                product_id.into_inner(),
-            )),
-            Err(message) => Err(ServiceError::new(
-                StatusCode::BAD_REQUEST,
-                message.to_string(),
-            )),
-        }
-    })
-    .await
-    .unwrap()
+    Ok(conn
+        .interact(move |conn| {
+            Product::get_product_subscription(conn, auth_user.id, product_id.into_inner())
+        })
+        .await
+        .unwrap())
 }
 
 pub async fn subscribe_to_product(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    body: web::Bytes,
+    auth_user: AuthUser,
     pool: &Data<Pool>,
 ) -> Result<(), ServiceError> {
+    // The conditions are optional, but malformed ones must not be ignored
+    let conditions = if body.is_empty() {
+        SubscriptionConditionsDTO::default()
+    } else {
+        serde_json::from_slice::<SubscriptionConditionsDTO>(&body).map_err(|e| {
+            ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid conditions: {}", e),
+            )
+        })?
+    };
+    conditions.validate().map_err(ServiceError::validation)?;
+
     let conn = &pool.get().await.unwrap();
 
     conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => match Product::subscribe_to_product(conn, user.id, product_id.into_inner())
-            {
-                Ok(code) => {
-                    if code == 0 {
-                        return Err(ServiceError::new(
-                            StatusCode::BAD_REQUEST,
-                            "Subscription already exists".to_string(),
-                        ));
-                    }
-                    Ok(())
+        // Price-drop notifications must not go to unconfirmed addresses
+        if !auth_user.email_verified {
+            return Err(ServiceError::new(
+                StatusCode::FORBIDDEN,
+                "Please verify your email before subscribing to products".to_string(),
+            ));
+        }
+
+        match Product::subscribe_to_product(conn, auth_user.id, product_id.into_inner(), conditions)
+        {
+            Ok(Ok(code)) => {
+                if code == 0 {
+                    return Err(ServiceError::new(
+                        StatusCode::BAD_REQUEST,
+                        "Subscription already exists".to_string(),
+                    ));
                 }
-                Err(message) => Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    message.to_string(),
-                )),
-            },
+                Ok(())
+            }
+            Ok(Err(field_errors)) => Err(ServiceError::validation(field_errors)),
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
                 message.to_string(),
@@ -129,31 +146,21 @@ pub async fn subscribe_to_product(
 
 pub async fn unsubscribe_from_product(
     product_id: web::Path<i32>,
-    token_claims: TokenClaims,
+    auth_user: AuthUser,
     pool: &Data<Pool>,
 ) -> Result<(), ServiceError> {
     let conn = &pool.get().await.unwrap();
 
     conn.interact(move |conn| {
-        let user = User::find_user_by_login(conn, &token_claims.login);
-
-        match user {
-            Ok(user) => {
-                match Product::unsubscribe_from_product(conn, user.id, product_id.into_inner()) {
-                    Ok(code) => {
-                        if code == 0 {
-                            return Err(ServiceError::new(
-                                StatusCode::BAD_REQUEST,
-                                "Subscription not found".to_string(),
-                            ));
-                        }
-                        Ok(())
-                    }
-                    Err(message) => Err(ServiceError::new(
+        match Product::unsubscribe_from_product(conn, auth_user.id, product_id.into_inner()) {
+            Ok(code) => {
+                if code == 0 {
+                    return Err(ServiceError::new(
                         StatusCode::BAD_REQUEST,
-                        message.to_string(),
-                    )),
+                        "Subscription not found".to_string(),
+                    ));
                 }
+                Ok(())
             }
             Err(message) => Err(ServiceError::new(
                 StatusCode::BAD_REQUEST,
@@ -164,3 +171,20 @@ pub async fn unsubscribe_from_product(
     .await
     .unwrap()
 }
+
+pub async fn add_price(
+    product_store_id: web::Path<i32>,
+    new_price: NewPriceDTO,
+    pool: &Data<Pool>,
+) -> Result<(), ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        match Product::add_price(conn, product_store_id.into_inner(), new_price) {
+            Ok(_) => Ok(()),
+            Err(message) => Err(ServiceError::new(StatusCode::BAD_REQUEST, message)),
+        }
+    })
+    .await
+    .unwrap()
+}
@@ -0,0 +1,89 @@
+use crate::errors::ServiceError;
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::user_notification_settings::{
+    UserNotificationSettings, UserNotificationSettingsDTO,
+};
+use crate::models::user_settings::{UserSettings, UserSettingsDTO};
+use crate::models::validation::Validate;
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use deadpool_diesel::postgres::Pool;
+
+pub async fn get_settings(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<Option<UserSettingsDTO>, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| match UserSettings::get(conn, auth_user.id) {
+        Ok(settings) => Ok(settings.map(UserSettingsDTO::from)),
+        Err(message) => Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            message.to_string(),
+        )),
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn update_settings(
+    auth_user: AuthUser,
+    settings: UserSettingsDTO,
+    pool: &Data<Pool>,
+) -> Result<UserSettingsDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match UserSettings::update(conn, auth_user.id, settings) {
+            Ok(Ok(settings)) => Ok(settings),
+            Ok(Err(field_errors)) => Err(ServiceError::validation(field_errors)),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub async fn get_notification_settings(
+    auth_user: AuthUser,
+    pool: &Data<Pool>,
+) -> Result<UserNotificationSettingsDTO, ServiceError> {
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(
+        move |conn| match UserNotificationSettings::get(conn, auth_user.id) {
+            Ok(settings) => Ok(settings),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub async fn update_notification_settings(
+    auth_user: AuthUser,
+    settings: UserNotificationSettingsDTO,
+    pool: &Data<Pool>,
+) -> Result<UserNotificationSettingsDTO, ServiceError> {
+    settings.validate().map_err(ServiceError::validation)?;
+
+    let conn = &pool.get().await.unwrap();
+
+    conn.interact(move |conn| {
+        match UserNotificationSettings::update(conn, auth_user.id, settings) {
+            Ok(settings) => Ok(settings),
+            Err(message) => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                message.to_string(),
+            )),
+        }
+    })
+    .await
+    .unwrap()
+}
@@ -0,0 +1,44 @@
+use crate::errors::ServiceError;
+use crate::middlewares::jwt_middleware::AuthUser;
+use crate::models::product::PriceStreamQuery;
+use crate::models::user::User;
+use crate::price_updates::PriceUpdates;
+use actix_web::http::StatusCode;
+use actix_web::web;
+use actix_web::web::Bytes;
+use deadpool_diesel::postgres::Pool;
+use futures_util::Stream;
+
+pub async fn price_stream(
+    query: PriceStreamQuery,
+    auth_user: Option<AuthUser>,
+    price_updates: &web::Data<PriceUpdates>,
+    pool: &web::Data<Pool>,
+) -> Result<impl Stream<Item = Bytes>, ServiceError> {
+    // Subscriptions made later aren't picked up until the client reconnects
+    let product_ids = match query
+        .parse_product_ids()
+        .map_err(ServiceError::validation)?
+    {
+        Some(product_ids) => product_ids,
+        None => {
+            let Some(auth_user) = auth_user else {
+                return Err(ServiceError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "Log in to stream the subscribed products, or pass product_ids".to_string(),
+                ));
+            };
+            let conn = &pool.get().await.unwrap();
+
+            conn.interact(move |conn| User::get_subscriptions(conn, auth_user.id))
+                .await
+                .unwrap()
+                .map_err(|message| ServiceError::new(StatusCode::BAD_REQUEST, message.to_string()))?
+                .into_iter()
+                .map(|subscription| subscription.product_id)
+                .collect()
+        }
+    };
+
+    Ok(price_updates.events(product_ids))
+}
@@ -0,0 +1,51 @@
+use crate::config::app::Config;
+use crate::notifications::digest;
+use crate::notifications::NotificationChannel;
+use deadpool_diesel::postgres::Pool;
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Sends the digests that are due every `DIGEST_INTERVAL_SECS`, in the background of
+/// the server
+pub fn start(pool: Pool, config: &Config, channels: Vec<Arc<dyn NotificationChannel>>) {
+    if config.digest_interval_secs == 0 || channels.is_empty() {
+        info!("Digests are disabled");
+        return;
+    }
+
+    let interval = Duration::from_secs(config.digest_interval_secs);
+    let config = config.clone();
+    let channels = Arc::new(channels);
+    actix_web::rt::spawn(async move {
+        loop {
+            send(&pool, &config, &channels).await;
+            actix_web::rt::time::sleep(interval).await;
+        }
+    });
+}
+
+async fn send(pool: &Pool, config: &Config, channels: &Arc<Vec<Arc<dyn NotificationChannel>>>) {
+    let conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to send digests: {}", e);
+            return;
+        }
+    };
+
+    let config = config.clone();
+    let channels = channels.clone();
+    match conn
+        .interact(move |conn| digest::send_due(conn, &channels, &config))
+        .await
+    {
+        Ok(Ok(report)) => {
+            if report.sent > 0 || report.failed > 0 {
+                info!("Sent {} digests, {} failed", report.sent, report.failed);
+            }
+        }
+        Ok(Err(e)) => error!("Failed to send digests: {}", e),
+        Err(e) => error!("Failed to send digests: {}", e),
+    }
+}
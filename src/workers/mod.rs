@@ -0,0 +1,4 @@
+pub mod digests;
+pub mod notifications;
+pub mod price_drops;
+pub mod price_updates;
@@ -0,0 +1,67 @@
+use crate::config::app::Config;
+use crate::notifications::delivery::{self, DELIVERY_BATCH_SIZE};
+use crate::notifications::NotificationChannel;
+use deadpool_diesel::postgres::Pool;
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Delivers due notifications every `NOTIFICATION_DELIVERY_INTERVAL_SECS`, in the
+/// background of the server
+pub fn start(pool: Pool, config: &Config, channels: Vec<Arc<dyn NotificationChannel>>) {
+    if config.notification_delivery_interval_secs == 0 || channels.is_empty() {
+        info!("Notification delivery is disabled");
+        return;
+    }
+
+    let interval = Duration::from_secs(config.notification_delivery_interval_secs);
+    let config = config.clone();
+    let channels = Arc::new(channels);
+    actix_web::rt::spawn(async move {
+        loop {
+            // Keep going while there is a backlog
+            while deliver(&pool, &config, &channels).await == DELIVERY_BATCH_SIZE as usize {}
+            actix_web::rt::time::sleep(interval).await;
+        }
+    });
+}
+
+// Returns how many notifications were claimed
+async fn deliver(
+    pool: &Pool,
+    config: &Config,
+    channels: &Arc<Vec<Arc<dyn NotificationChannel>>>,
+) -> usize {
+    let conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to deliver notifications: {}", e);
+            return 0;
+        }
+    };
+
+    let config = config.clone();
+    let channels = channels.clone();
+    match conn
+        .interact(move |conn| delivery::deliver_due(conn, &channels, &config))
+        .await
+    {
+        Ok(Ok(report)) => {
+            if report.claimed > 0 {
+                info!(
+                    "Delivered {} of {} notifications, {} postponed, {} failed",
+                    report.delivered, report.claimed, report.postponed, report.failed
+                );
+            }
+            report.claimed
+        }
+        Ok(Err(e)) => {
+            error!("Failed to deliver notifications: {}", e);
+            0
+        }
+        Err(e) => {
+            error!("Failed to deliver notifications: {}", e);
+            0
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::config::app::Config;
+use crate::models::notification::PriceDrop;
+use deadpool_diesel::postgres::Pool;
+use log::{error, info};
+use std::time::Duration;
+
+/// Checks subscribed products for price drops every `PRICE_CHECK_INTERVAL_SECS`,
+/// in the background of the server
+pub fn start(pool: Pool, config: &Config) {
+    if config.price_check_interval_secs == 0 {
+        info!("Price drop detection is disabled");
+        return;
+    }
+
+    let interval = Duration::from_secs(config.price_check_interval_secs);
+    info!(
+        "Checking for price drops every {} seconds",
+        interval.as_secs()
+    );
+    actix_web::rt::spawn(async move {
+        loop {
+            check(&pool).await;
+            // Waiting after the check, so a slow one doesn't run back to back
+            actix_web::rt::time::sleep(interval).await;
+        }
+    });
+}
+
+async fn check(pool: &Pool) {
+    let conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to check for price drops: {}", e);
+            return;
+        }
+    };
+
+    match conn.interact(PriceDrop::detect).await {
+        Ok(Ok(price_drops)) => {
+            for price_drop in price_drops {
+                match price_drop.old_price {
+                    Some(old_price) => info!(
+                        "Price of product {} dropped from {} to {} for user {} (notification {})",
+                        price_drop.product_id,
+                        old_price,
+                        price_drop.new_price,
+                        price_drop.user_id,
+                        price_drop.notification_id
+                    ),
+                    None => info!(
+                        "Price of product {} met the target at {} for user {} (notification {})",
+                        price_drop.product_id,
+                        price_drop.new_price,
+                        price_drop.user_id,
+                        price_drop.notification_id
+                    ),
+                }
+            }
+        }
+        Ok(Err(e)) => error!("Failed to check for price drops: {}", e),
+        Err(e) => error!("Failed to check for price drops: {}", e),
+    }
+}
@@ -0,0 +1,20 @@
+use crate::price_updates::connection::ListenerConfig;
+use crate::price_updates::PriceUpdates;
+use log::error;
+use std::time::Duration;
+
+// Prices added while reconnecting aren't streamed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Listens for the prices added by any instance, in the background of the server,
+/// reconnecting whenever the database connection is lost
+pub fn start(listener_config: ListenerConfig, price_updates: PriceUpdates) {
+    actix_web::rt::spawn(async move {
+        loop {
+            if let Err(e) = price_updates.listen(&listener_config).await {
+                error!("Failed to listen for price updates: {}", e);
+            }
+            actix_web::rt::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}